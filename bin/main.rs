@@ -1,5 +1,7 @@
+use apicius::quantity::Rational;
 use apicius::types::ToPrintable;
-use apicius::{checks, grammar, render, types};
+use apicius::{checks, render, scale, schedule, shopping, timing, types, units};
+use std::collections::BTreeMap;
 
 mod opts;
 
@@ -16,42 +18,312 @@ fn realmain() -> Result<(), Box<dyn std::error::Error>> {
     let mut output = opts.get_output()?;
 
     let mut s = types::State::new();
-    // TODO: convert these errors
-    let recipe = grammar::RecipeParser::new().parse(&mut s, &input).unwrap();
+    let mut cookbook = match apicius::parse_cookbook(&mut s, &input) {
+        Ok(cookbook) => cookbook,
+        Err(err) => {
+            eprint!("{}", err.render(&input));
+            std::process::exit(1);
+        }
+    };
+
+    // either the one recipe we asked for, or everything in the file
+    let selected: Vec<usize> = match &opts.recipe {
+        Some(name) => match cookbook.position(&s, name) {
+            Some(idx) => vec![idx],
+            None => return Err(format!("no recipe named '{}'", name).into()),
+        },
+        None => (0..cookbook.recipes.len()).collect(),
+    };
+
+    if let opts::ApiciusCommand::Scale(scale_by) = &opts.command {
+        let mut warnings = Vec::new();
+        for i in selected.iter() {
+            let recipe = &mut cookbook.recipes[*i];
+            let factor = match scale_by {
+                opts::ScaleBy::Factor(factor) => parse_number(factor)?,
+                opts::ScaleBy::Servings(target) => match scale::servings(&s, recipe) {
+                    Some(servings) => parse_number(target)? / servings,
+                    None => {
+                        return Err(format!(
+                            "recipe '{}' doesn't say how many servings it makes (or says none)",
+                            &s[recipe.name]
+                        )
+                        .into())
+                    }
+                },
+            };
+            warnings.extend(scale::scale(&mut s, recipe, factor));
+        }
+        for warning in warnings.iter() {
+            eprint!("{}", warning.render(&input));
+        }
+    }
+
+    // converting units happens after scaling, so that scaling still
+    // works in whatever units the author used
+    if let Some(system) = opts.units {
+        convert_units(&mut s, &cookbook, system, &opts.densities)?;
+    }
+
+    if let opts::ApiciusCommand::Scale(_) = opts.command {
+        for (n, i) in selected.iter().enumerate() {
+            if n > 0 {
+                writeln!(output)?;
+            }
+            s.debug_recipe(&mut output, &cookbook.recipes[*i])?;
+        }
+        return Ok(());
+    }
+
+    let recipes: Vec<&types::Recipe> = selected.iter().map(|i| &cookbook.recipes[*i]).collect();
 
     if let opts::ApiciusCommand::DebugParseTree = opts.command {
-        s.debug_recipe(&mut output, &recipe)?;
+        for (i, recipe) in recipes.iter().enumerate() {
+            if i > 0 {
+                writeln!(output)?;
+            }
+            s.debug_recipe(&mut output, recipe)?;
+        }
         return Ok(());
     }
 
-    let analysis = checks::Analysis::from_recipe(&s, &recipe);
+    // we analyze everything, even if we're only rendering one recipe,
+    // because it might use the others
+    let analyses = checks::Analysis::from_cookbook(&s, &cookbook);
 
     if let opts::ApiciusCommand::DebugAnalysis = opts.command {
-        writeln!(output, "{:#?}", analysis.printable(&s))?;
+        for i in selected.iter() {
+            writeln!(output, "{:#?}", analyses[*i].printable(&s))?;
+        }
         return Ok(());
     }
 
-    let tree = analysis.into_tree()?;
+    // the graph is meant for tracking down problems, so it gets drawn
+    // whether or not there are any
+    if let opts::ApiciusCommand::Dot = opts.command {
+        let selected: Vec<&checks::Analysis> = selected.iter().map(|i| &analyses[*i]).collect();
+        write!(output, "{}", render::dot::dot(&s, &selected))?;
+        return Ok(());
+    }
+
+    let diagnostics: Vec<String> = selected
+        .iter()
+        .flat_map(|i| analyses[*i].diagnostics(&s))
+        .map(|d| d.render(&input))
+        .collect();
+    if !diagnostics.is_empty() {
+        eprint!("{}", diagnostics.join("\n"));
+        std::process::exit(1);
+    }
+
+    // the recipes we didn't select only matter if we're inlining
+    // them, so it's fine if they can't be turned into trees
+    let mut all_trees = BTreeMap::new();
+    let mut trees = Vec::new();
+    for (i, analysis) in analyses.into_iter().enumerate() {
+        let name = cookbook.recipes[i].name.value;
+        if selected.contains(&i) {
+            let tree = analysis.into_tree()?;
+            all_trees.insert(name, tree.clone());
+            trees.push(tree);
+        } else if let Ok(tree) = analysis.into_tree() {
+            all_trees.insert(name, tree);
+        }
+    }
+    if opts.inline_recipes {
+        trees = trees
+            .iter()
+            .map(|tree| tree.inline_sub_recipes(&s, &all_trees))
+            .collect();
+    }
+    let pairs: Vec<(&types::Recipe, &checks::BackwardTree)> =
+        recipes.iter().copied().zip(trees.iter()).collect();
 
     if let opts::ApiciusCommand::DebugBackwardTree = opts.command {
-        writeln!(output, "{:#?}", tree.printable(&s))?;
+        for tree in trees.iter() {
+            writeln!(output, "{:#?}", tree.printable(&s))?;
+        }
+        return Ok(());
+    }
+
+    if let opts::ApiciusCommand::Timing = opts.command {
+        for (n, (recipe, tree)) in pairs.iter().enumerate() {
+            for warning in timing::unreadable_times(&s, tree) {
+                eprint!("{}", warning.render(&input));
+            }
+            if n > 0 {
+                writeln!(output)?;
+            }
+            writeln!(output, "{}:", &s[recipe.name])?;
+            timing::Timing::from_tree(tree).debug(&mut output, &s)?;
+        }
+        return Ok(());
+    }
+
+    if let opts::ApiciusCommand::Schedule { cooks, format } = &opts.command {
+        let cooks = match cooks.trim().parse::<usize>() {
+            Ok(n) if n > 0 => n,
+            _ => return Err(format!("'{}' isn't a number of cooks", cooks).into()),
+        };
+        for (n, (recipe, tree)) in pairs.iter().enumerate() {
+            for warning in timing::unreadable_times(&s, tree) {
+                eprint!("{}", warning.render(&input));
+            }
+            let plan = schedule::Schedule::new(tree, cooks);
+            match format {
+                opts::ScheduleFormat::Text => {
+                    if n > 0 {
+                        writeln!(output)?;
+                    }
+                    writeln!(output, "{}:", &s[recipe.name])?;
+                    write!(output, "{}", plan.text(&s))?;
+                }
+                opts::ScheduleFormat::Html => {
+                    if recipes.len() > 1 {
+                        writeln!(output, "{}", render::html::recipe_heading(&s[recipe.name]))?;
+                    }
+                    write!(output, "{}", render::gantt::Gantt::new(&s, &plan).html())?;
+                }
+                opts::ScheduleFormat::Svg => {
+                    write!(output, "{}", render::gantt::Gantt::new(&s, &plan).svg())?;
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    if let opts::ApiciusCommand::ShoppingList = opts.command {
+        // this works from the trees before inlining, so that inlined
+        // recipes don't get counted twice
+        let list = shopping::ShoppingList::for_recipes(&s, &cookbook, &all_trees, &recipes);
+        write!(output, "{}", list.text())?;
+        return Ok(());
+    }
+
+    if let opts::ApiciusCommand::Graph(format) = opts.command {
+        let tree = only_one(&trees)?;
+        let config = render::drawing::RenderConfig::default();
+        let graph = render::graph::layout(&s, tree, &config)?;
+        write_image(&graph, format, &mut output)?;
+        return Ok(());
+    }
+
+    if let opts::ApiciusCommand::Instructions(format) = opts.command {
+        for (n, (recipe, tree)) in pairs.iter().enumerate() {
+            let instructions = render::instructions::Instructions::new(&s, tree);
+            match format {
+                opts::TextFormat::Text => {
+                    if n > 0 {
+                        writeln!(output)?;
+                    }
+                    writeln!(output, "{}:", &s[recipe.name])?;
+                    write!(output, "{}", instructions.text())?;
+                }
+                opts::TextFormat::Markdown => {
+                    if n > 0 {
+                        writeln!(output)?;
+                    }
+                    writeln!(output, "## {}\n", &s[recipe.name])?;
+                    write!(output, "{}", instructions.markdown())?;
+                }
+                opts::TextFormat::Html => {
+                    if recipes.len() > 1 {
+                        writeln!(output, "{}", render::html::recipe_heading(&s[recipe.name]))?;
+                    }
+                    write!(output, "{}", instructions.html())?;
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    if let opts::ApiciusCommand::Latex(latex_options) = &opts.command {
+        write!(
+            output,
+            "{}",
+            render::latex::latex(&s, &pairs, latex_options)
+        )?;
+        return Ok(());
+    }
+
+    if let opts::ApiciusCommand::Markdown(markdown_options) = &opts.command {
+        write!(
+            output,
+            "{}",
+            render::markdown::markdown(&s, &pairs, markdown_options)
+        )?;
+        return Ok(());
+    }
+
+    if let opts::ApiciusCommand::Mermaid(mermaid_options) = &opts.command {
+        write!(
+            output,
+            "{}",
+            render::mermaid::mermaid(&s, &pairs, mermaid_options)
+        )?;
+        return Ok(());
+    }
+
+    if let opts::ApiciusCommand::RecipeCards(card_options) = &opts.command {
+        render::card::Cards::new(&s, &pairs, card_options)?.pdf(&mut output)?;
         return Ok(());
     }
 
     if opts.command.is_table_command() {
-        let table = render::table::Table::new(&s, &tree);
+        let tables: Vec<render::table::Table> = trees
+            .iter()
+            .map(|tree| render::table::Table::new(&s, tree))
+            .collect();
+
+        if let opts::ApiciusCommand::TableImage(format) = opts.command {
+            let table = only_one(&tables)?;
+            let config = render::drawing::RenderConfig::default();
+            write_image(&table.drawing(&config)?, format, &mut output)?;
+            return Ok(());
+        }
+
+        if let opts::ApiciusCommand::TextTable(text_options, color) = &opts.command {
+            let mut text_options = text_options.clone();
+            text_options.color = color.unwrap_or_else(|| opts.writes_to_terminal());
+            for (n, (recipe, table)) in recipes.iter().zip(tables.iter()).enumerate() {
+                if recipes.len() > 1 {
+                    if n > 0 {
+                        writeln!(output)?;
+                    }
+                    writeln!(output, "{}:", &s[recipe.name])?;
+                }
+                write!(output, "{}", table.text(&text_options))?;
+            }
+            return Ok(());
+        }
 
         if let opts::ApiciusCommand::DebugTable = opts.command {
-            writeln!(output, "{}", table.debug())?;
+            for table in tables.iter() {
+                writeln!(output, "{}", table.debug())?;
+            }
             return Ok(());
         }
 
-        if let opts::ApiciusCommand::HTMLTable(opts) = opts.command {
+        if let opts::ApiciusCommand::HTMLTable(mut opts, theme) = opts.command {
+            if let Some(theme) = theme {
+                opts.html_header = render::theme::Theme::load(&theme)?.html_header();
+            }
             if opts.standalone {
                 writeln!(output, "{}", opts.html_header)?;
             }
+            // only recipes with a heading have anything to link to
+            if recipes.len() > 1 {
+                opts.linked_recipes = recipes.iter().map(|r| s[r.name].to_string()).collect();
+            }
 
-            writeln!(output, "{}", table.html(&opts))?;
+            for (recipe, table) in recipes.iter().zip(tables.iter()) {
+                // with more than one table, we need some way of
+                // telling them apart
+                if recipes.len() > 1 {
+                    writeln!(output, "{}", render::html::recipe_heading(&s[recipe.name]))?;
+                }
+                writeln!(output, "{}", table.html(&opts)?)?;
+            }
 
             if opts.standalone {
                 writeln!(output, "{}", opts.html_footer)?;
@@ -63,3 +335,50 @@ fn realmain() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Images only have room for one recipe, so this makes sure that's
+/// all we've got
+fn only_one<T>(rendered: &[T]) -> Result<&T, Box<dyn std::error::Error>> {
+    match rendered {
+        [one] => Ok(one),
+        _ => Err("this file has more than one recipe, so pick one to draw with --recipe".into()),
+    }
+}
+
+fn write_image(
+    drawing: &render::drawing::Drawing,
+    format: opts::ImageFormat,
+    output: &mut impl std::io::Write,
+) -> Result<(), cairo::IoError> {
+    match format {
+        opts::ImageFormat::Png => drawing.png(output),
+        opts::ImageFormat::Svg => drawing.svg(output),
+    }
+}
+
+/// Parse a positive number given on the command line, like `2`, `1.5`
+/// or `3/4`
+fn parse_number(s: &str) -> Result<Rational, Box<dyn std::error::Error>> {
+    match Rational::parse(s.trim()) {
+        Some(n) if n > Rational::from_integer(0) => Ok(n),
+        _ => Err(format!("'{}' isn't a positive number", s).into()),
+    }
+}
+
+/// Rewrite the amounts in every recipe into the given units, using the
+/// density table at `densities` if we were given one
+fn convert_units(
+    s: &mut types::State,
+    cookbook: &types::Cookbook,
+    system: units::System,
+    densities: &Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let densities = match densities {
+        Some(path) => units::DensityTable::parse(&std::fs::read_to_string(path)?)?,
+        None => units::DensityTable::new(),
+    };
+    for recipe in cookbook.recipes.iter() {
+        units::convert_recipe(s, recipe, system, &densities);
+    }
+    Ok(())
+}
@@ -1,31 +1,73 @@
-use apicius::render::table::HTMLTableOptions;
+use apicius::render::card::{CardOptions, Diagram};
+use apicius::render::latex::LatexOptions;
+use apicius::render::markdown::{Embed, MarkdownOptions};
+use apicius::render::mermaid::MermaidOptions;
+use apicius::render::table::{HTMLTableOptions, TextTableOptions};
+use apicius::units::System;
 
-use clap::{arg, command, ArgMatches, Command};
+use clap::{arg, command, ArgGroup, ArgMatches, Command, ErrorKind};
 
 use std::io;
 use std::io::Read;
 
+/// The `html-table` options that don't mean anything when it's
+/// drawing an SVG instead
+const HTML_ONLY_ARGS: &[&str] = &[
+    "standalone",
+    "theme",
+    "html_header",
+    "html_footer",
+    "amount_class",
+    "seasonings_class",
+    "ingredient_class",
+    "sub_recipe_class",
+    "action_class",
+    "time_class",
+    "done_class",
+];
+
 #[derive(Debug)]
 pub struct Opts {
     pub command: ApiciusCommand,
     pub input: Option<String>,
     pub output: Option<String>,
+    pub recipe: Option<String>,
+    pub inline_recipes: bool,
+    pub units: Option<System>,
+    pub densities: Option<String>,
 }
 
 impl Opts {
-    fn subcommand(name: &str) -> Command {
-        Command::new(name).arg(arg!([INPUT])).arg(arg!([OUTPUT]))
+    fn subcommand(name: &str) -> Command<'_> {
+        Command::new(name)
+            .arg(arg!([INPUT]))
+            .arg(arg!([OUTPUT]))
+            .arg(arg!(--recipe <RECIPE> "Only use the recipe with this name").required(false))
+            .arg(arg!(--"inline-recipes" "Draw @recipe ingredients as part of the recipes using them"))
+            .arg(
+                arg!(--units <UNITS> "Convert every amount into these units")
+                    .required(false)
+                    .possible_values(["metric", "us"]),
+            )
+            .arg(
+                arg!(--densities <FILE> "Ingredient densities to use when converting units")
+                    .required(false),
+            )
     }
 
-    fn handle_subcommand(
-        cmd: ApiciusCommand,
-        opts: &ArgMatches,
-    ) -> (ApiciusCommand, Option<String>, Option<String>) {
-        (
-            cmd,
-            opts.value_of("INPUT").map(|s| s.to_string()),
-            opts.value_of("OUTPUT").map(|s| s.to_string()),
-        )
+    fn handle_subcommand(command: ApiciusCommand, opts: &ArgMatches) -> Opts {
+        Opts {
+            command,
+            input: opts.value_of("INPUT").map(|s| s.to_string()),
+            output: opts.value_of("OUTPUT").map(|s| s.to_string()),
+            recipe: opts.value_of("recipe").map(|s| s.to_string()),
+            inline_recipes: opts.is_present("inline-recipes"),
+            units: opts.value_of("units").map(|s| {
+                s.parse()
+                    .expect("clap should only allow known unit systems")
+            }),
+            densities: opts.value_of("densities").map(|s| s.to_string()),
+        }
     }
 
     pub fn parse() -> Opts {
@@ -38,20 +80,147 @@ impl Opts {
                 Opts::subcommand("debug-backward-tree").about("Print the generated backward tree"),
             )
             .subcommand(Opts::subcommand("debug-table").about("Print the raw table layout info"))
+            .subcommand(
+                Opts::subcommand("dot")
+                    .about("Print a Graphviz graph of the recipe, showing any problems in red"),
+            )
+            .subcommand(
+                Opts::subcommand("instructions")
+                    .about("Write the recipe out as numbered steps")
+                    .arg(
+                        arg!(--format <FORMAT> "How to write the steps")
+                            .required(false)
+                            .possible_values(["text", "markdown", "html"])
+                            .default_value("text"),
+                    ),
+            )
+            .subcommand(
+                Opts::subcommand("latex")
+                    .about("Write the recipe out as a LaTeX table")
+                    .arg(arg!(--standalone "Write a whole document rather than a fragment"))
+                    .arg(arg!(--graph "Draw a TikZ graph of the recipe too")),
+            )
+            .subcommand(
+                Opts::subcommand("markdown")
+                    .about("Write the recipe out as a Markdown document")
+                    .arg(
+                        arg!(--embed <DIAGRAM> "A diagram to put after the method")
+                            .required(false)
+                            .possible_values(["none", "table", "mermaid"])
+                            .default_value("none"),
+                    ),
+            )
+            .subcommand(
+                Opts::subcommand("mermaid")
+                    .about("Print a Mermaid flowchart of the recipe")
+                    .arg(arg!(--"code-fence" "Wrap the flowchart in a Markdown code block")),
+            )
+            .subcommand(
+                Opts::subcommand("png-graph")
+                    .about("Draw the recipe as a graph")
+                    .arg(
+                        arg!(--format <FORMAT> "What kind of image to draw")
+                            .required(false)
+                            .possible_values(["png", "svg"])
+                            .default_value("png"),
+                    ),
+            )
             .subcommand(
                 Opts::subcommand("html-table")
                     .about("Convert the recipe to an HTML table")
+                    .arg(
+                        arg!(--format <FORMAT> "Write HTML, or draw the table as an image")
+                            .required(false)
+                            .possible_values(["html", "svg"])
+                            .default_value("html"),
+                    )
                     .arg(arg!(--standalone).required(false))
+                    .arg(
+                        arg!(--theme <THEME> "A built-in theme (default, light, dark, print or high-contrast) or a CSS file")
+                            .required(false)
+                            .conflicts_with("html_header"),
+                    )
                     .arg(arg!(--html_header <HTML_HEADER>).required(false))
                     .arg(arg!(--html_footer <HTML_FOOTER>).required(false))
                     .arg(arg!(--amount_class <AMOUNT_CLASS>).required(false))
                     .arg(arg!(--seasonings_class <SEASONINGS_CLASS>).required(false))
                     .arg(arg!(--ingredient_class <INGREDIENT_CLASS>).required(false))
+                    .arg(arg!(--sub_recipe_class <SUB_RECIPE_CLASS>).required(false))
                     .arg(arg!(--action_class <ACTION_CLASS>).required(false))
+                    .arg(arg!(--time_class <TIME_CLASS>).required(false))
                     .arg(arg!(--done_class <DONE_CLASS>).required(false)),
             )
+            .subcommand(
+                Opts::subcommand("text-table")
+                    .about("Draw the recipe as a table for the terminal")
+                    .arg(
+                        arg!(--width <WIDTH> "How many columns of text fit in a cell before it wraps")
+                            .required(false)
+                            .validator(|s| s.parse::<std::num::NonZeroUsize>())
+                            .default_value("24"),
+                    )
+                    .arg(arg!(--ascii "Draw the lines with plain ASCII characters"))
+                    .arg(
+                        arg!(--color <WHEN> "Color ingredients, steps and <>")
+                            .required(false)
+                            .possible_values(["auto", "always", "never"])
+                            .default_value("auto"),
+                    ),
+            )
+            .subcommand(
+                Opts::subcommand("recipe-cards")
+                    .about("Make printable PDF recipe cards")
+                    .arg(
+                        arg!(--"page-size" <SIZE> "The paper to print on")
+                            .required(false)
+                            .possible_values(["a4", "letter", "index-card"])
+                            .default_value("a4"),
+                    )
+                    .arg(
+                        arg!(--diagram <DIAGRAM> "How to draw the steps")
+                            .required(false)
+                            .possible_values(["table", "graph"])
+                            .default_value("table"),
+                    ),
+            )
+            .subcommand(
+                Opts::subcommand("scale")
+                    .about("Print the recipe with every amount scaled up or down")
+                    .arg(arg!(--factor <FACTOR> "Multiply every amount by this").required(false))
+                    .arg(
+                        arg!(--servings <SERVINGS> "Scale the recipe to make this many servings")
+                            .required(false),
+                    )
+                    .group(
+                        ArgGroup::new("scale_by")
+                            .args(&["factor", "servings"])
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                Opts::subcommand("shopping-list")
+                    .about("List everything needed to make the recipes"),
+            )
+            .subcommand(
+                Opts::subcommand("timing").about("Work out how long the recipes take to make"),
+            )
+            .subcommand(
+                Opts::subcommand("schedule")
+                    .about("Plan who does each step and when")
+                    .arg(
+                        arg!(--cooks <COOKS> "How many people are cooking")
+                            .required(false)
+                            .default_value("1"),
+                    )
+                    .arg(
+                        arg!(--format <FORMAT> "How to show the schedule")
+                            .required(false)
+                            .possible_values(["text", "html", "svg"])
+                            .default_value("text"),
+                    ),
+            )
             .get_matches();
-        let (command, input, output) = match matches.subcommand() {
+        match matches.subcommand() {
             // the basic debug ones
             Some(("debug-parse-tree", opts)) => {
                 Opts::handle_subcommand(ApiciusCommand::DebugParseTree, opts)
@@ -65,10 +234,65 @@ impl Opts {
             Some(("debug-table", opts)) => {
                 Opts::handle_subcommand(ApiciusCommand::DebugTable, opts)
             }
+            Some(("dot", opts)) => Opts::handle_subcommand(ApiciusCommand::Dot, opts),
+            Some(("instructions", opts)) => {
+                let format = match opts.value_of("format") {
+                    Some("markdown") => TextFormat::Markdown,
+                    Some("html") => TextFormat::Html,
+                    _ => TextFormat::Text,
+                };
+                Opts::handle_subcommand(ApiciusCommand::Instructions(format), opts)
+            }
+            Some(("latex", opts)) => {
+                let latex_options = LatexOptions {
+                    standalone: opts.is_present("standalone"),
+                    graph: opts.is_present("graph"),
+                };
+                Opts::handle_subcommand(ApiciusCommand::Latex(latex_options), opts)
+            }
+            Some(("markdown", opts)) => {
+                let markdown_options = MarkdownOptions {
+                    embed: match opts.value_of("embed") {
+                        Some("table") => Some(Embed::Table),
+                        Some("mermaid") => Some(Embed::Mermaid),
+                        _ => None,
+                    },
+                };
+                Opts::handle_subcommand(ApiciusCommand::Markdown(markdown_options), opts)
+            }
+            Some(("mermaid", opts)) => {
+                let mermaid_options = MermaidOptions {
+                    code_fence: opts.is_present("code-fence"),
+                };
+                Opts::handle_subcommand(ApiciusCommand::Mermaid(mermaid_options), opts)
+            }
+            Some(("png-graph", opts)) => {
+                let format = match opts.value_of("format") {
+                    Some("svg") => ImageFormat::Svg,
+                    _ => ImageFormat::Png,
+                };
+                Opts::handle_subcommand(ApiciusCommand::Graph(format), opts)
+            }
             // table plus table options
+            Some(("html-table", opts)) if opts.value_of("format") == Some("svg") => {
+                // clap can only say two arguments conflict, not that an
+                // argument conflicts with one value of another
+                if let Some(arg) = HTML_ONLY_ARGS.iter().find(|arg| opts.is_present(arg)) {
+                    clap::Error::raw(
+                        ErrorKind::ArgumentConflict,
+                        format!("'--{}' only applies to HTML, not '--format svg'\n", arg),
+                    )
+                    .exit();
+                }
+                Opts::handle_subcommand(ApiciusCommand::TableImage(ImageFormat::Svg), opts)
+            }
             Some(("html-table", opts)) => {
-                let mut html_options = HTMLTableOptions::default();
-                html_options.standalone = opts.is_present("standalone");
+                // a theme only goes in the page header, so asking for
+                // one means asking for a whole page
+                let mut html_options = HTMLTableOptions {
+                    standalone: opts.is_present("standalone") || opts.is_present("theme"),
+                    ..Default::default()
+                };
 
                 if let Some(s) = opts.value_of("html_header") {
                     html_options.html_header = s.to_string();
@@ -86,21 +310,78 @@ impl Opts {
                 if let Some(s) = opts.value_of("ingredient_class") {
                     html_options.ingredient_class = s.to_string();
                 }
+                if let Some(s) = opts.value_of("sub_recipe_class") {
+                    html_options.sub_recipe_class = s.to_string();
+                }
                 if let Some(s) = opts.value_of("action_class") {
                     html_options.action_class = s.to_string();
                 }
+                if let Some(s) = opts.value_of("time_class") {
+                    html_options.time_class = s.to_string();
+                }
                 if let Some(s) = opts.value_of("done_class") {
                     html_options.done_class = s.to_string();
                 }
-                Opts::handle_subcommand(ApiciusCommand::HTMLTable(html_options), opts)
+                let theme = opts.value_of("theme").map(|s| s.to_string());
+                Opts::handle_subcommand(ApiciusCommand::HTMLTable(html_options, theme), opts)
+            }
+            Some(("text-table", opts)) => {
+                let text_options = TextTableOptions {
+                    unicode: !opts.is_present("ascii"),
+                    wrap_width: opts
+                        .value_of("width")
+                        .unwrap()
+                        .parse()
+                        .expect("clap should only allow numbers"),
+                    ..Default::default()
+                };
+                let color = match opts.value_of("color") {
+                    Some("always") => Some(true),
+                    Some("never") => Some(false),
+                    _ => None,
+                };
+                Opts::handle_subcommand(ApiciusCommand::TextTable(text_options, color), opts)
+            }
+            Some(("recipe-cards", opts)) => {
+                let card_options = CardOptions {
+                    page_size: opts
+                        .value_of("page-size")
+                        .unwrap()
+                        .parse()
+                        .expect("clap should only allow known page sizes"),
+                    diagram: match opts.value_of("diagram") {
+                        Some("graph") => Diagram::Graph,
+                        _ => Diagram::Table,
+                    },
+                    ..Default::default()
+                };
+                Opts::handle_subcommand(ApiciusCommand::RecipeCards(card_options), opts)
+            }
+            Some(("scale", opts)) => {
+                let scale_by = if let Some(factor) = opts.value_of("factor") {
+                    ScaleBy::Factor(factor.to_string())
+                } else {
+                    ScaleBy::Servings(opts.value_of("servings").unwrap().to_string())
+                };
+                Opts::handle_subcommand(ApiciusCommand::Scale(scale_by), opts)
+            }
+            Some(("shopping-list", opts)) => {
+                Opts::handle_subcommand(ApiciusCommand::ShoppingList, opts)
+            }
+            Some(("timing", opts)) => Opts::handle_subcommand(ApiciusCommand::Timing, opts),
+            Some(("schedule", opts)) => {
+                let format = match opts.value_of("format") {
+                    Some("html") => ScheduleFormat::Html,
+                    Some("svg") => ScheduleFormat::Svg,
+                    _ => ScheduleFormat::Text,
+                };
+                let command = ApiciusCommand::Schedule {
+                    cooks: opts.value_of("cooks").unwrap().to_string(),
+                    format,
+                };
+                Opts::handle_subcommand(command, opts)
             }
             _ => unreachable!("Unhandled subcommand"),
-        };
-
-        Opts {
-            command,
-            input,
-            output,
         }
     }
 
@@ -121,6 +402,16 @@ impl Opts {
         std::fs::read_to_string(path)
     }
 
+    /// Whether we're writing to a terminal, rather than a file or a
+    /// pipe
+    pub fn writes_to_terminal(&self) -> bool {
+        use std::io::IsTerminal;
+        match self.output.as_deref() {
+            None | Some("-") => io::stdout().is_terminal(),
+            Some(_) => false,
+        }
+    }
+
     pub fn get_output(&self) -> io::Result<Box<dyn io::Write>> {
         let path = if let Some(path) = &self.output {
             path
@@ -138,18 +429,71 @@ impl Opts {
 
 #[derive(Debug)]
 pub enum ApiciusCommand {
-    HTMLTable(HTMLTableOptions),
+    /// the table options, plus the theme to load for a standalone page
+    HTMLTable(HTMLTableOptions, Option<String>),
     DebugParseTree,
     DebugAnalysis,
     DebugBackwardTree,
     DebugTable,
+    Dot,
+    Graph(ImageFormat),
+    Instructions(TextFormat),
+    Latex(LatexOptions),
+    Markdown(MarkdownOptions),
+    Mermaid(MermaidOptions),
+    TableImage(ImageFormat),
+    /// the table as text, along with whether to color it, or `None`
+    /// to color it only when it's going to a terminal
+    TextTable(TextTableOptions, Option<bool>),
+    RecipeCards(CardOptions),
+    Scale(ScaleBy),
+    ShoppingList,
+    Timing,
+    Schedule {
+        cooks: String,
+        format: ScheduleFormat,
+    },
+}
+
+/// The two ways of asking for a recipe to be scaled, kept as the
+/// text given on the command line
+#[derive(Debug)]
+pub enum ScaleBy {
+    Factor(String),
+    Servings(String),
+}
+
+/// The kinds of image we can draw with cairo
+#[derive(Debug, Copy, Clone)]
+pub enum ImageFormat {
+    Png,
+    Svg,
+}
+
+/// The kinds of document we can write text out as
+#[derive(Debug, Copy, Clone)]
+pub enum TextFormat {
+    Text,
+    Markdown,
+    Html,
+}
+
+/// The ways a schedule can be printed
+#[derive(Debug, Copy, Clone)]
+pub enum ScheduleFormat {
+    Text,
+    Html,
+    Svg,
 }
 
 impl ApiciusCommand {
     pub fn is_table_command(&self) -> bool {
         matches!(
             self,
-            ApiciusCommand::HTMLTable { .. } | ApiciusCommand::DebugTable
+            ApiciusCommand::HTMLTable(..)
+                | ApiciusCommand::DebugTable
+                | ApiciusCommand::TableImage(_)
+                | ApiciusCommand::TextTable(..)
         )
     }
 }
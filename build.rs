@@ -8,7 +8,6 @@ const FILE_PREFIX: &str = "
 use pretty_assertions::{assert_eq};
 
 use crate::types::*;
-use crate::grammar;
 use crate::checks;
 use crate::render;
 
@@ -28,6 +27,154 @@ impl<'a> core::fmt::Debug for StringWrapper<'a> {
 fn assert_eq(x: &str, y: &str) {
   assert_eq!(StringWrapper {wrapped: x}, StringWrapper {wrapped: y});
 }
+
+// checks that every tag in a piece of HTML is closed in the right
+// order, and that nothing in the text between them could be mistaken
+// for markup
+fn assert_well_formed(html: &str) {
+  let mut open: Vec<&str> = Vec::new();
+  let mut rest = html;
+  loop {
+    let text_end = rest.find('<').unwrap_or(rest.len());
+    let text = &rest[..text_end];
+    assert!(!text.contains('>'), \"stray '>' in {:?}\", text);
+    for (n, _) in text.match_indices('&') {
+      let entity = &text[n + 1..];
+      let end = entity.find(';').unwrap_or_else(|| panic!(\"bare '&' in {:?}\", text));
+      assert!(
+        entity[..end].chars().all(|c| c.is_ascii_alphanumeric() || c == '#'),
+        \"bare '&' in {:?}\",
+        text,
+      );
+    }
+    if text_end == rest.len() {
+      break;
+    }
+    rest = &rest[text_end..];
+    let tag_end = rest.find('>').unwrap_or_else(|| panic!(\"unclosed tag in {:?}\", rest));
+    let tag = &rest[1..tag_end];
+    rest = &rest[tag_end + 1..];
+    assert_eq!(tag.matches('\"').count() % 2, 0, \"unbalanced quotes in <{}>\", tag);
+    if let Some(name) = tag.strip_prefix('/') {
+      assert_eq!(open.pop(), Some(name), \"mismatched </{}>\", name);
+    } else if !tag.ends_with('/') && !tag.starts_with('!') {
+      open.push(tag.split_whitespace().next().unwrap());
+    }
+  }
+  assert!(open.is_empty(), \"unclosed tags: {:?}\", open);
+}
+
+#[test]
+fn test_html_class_names() {
+  use render::table::HTMLTableOptions;
+  assert!(HTMLTableOptions::default().validate().is_ok());
+  for bad in [\"\", \"1st\", \"two words\", \"x\\\" onclick=\\\"alert(1)\", \"<b>\"] {
+    let opts = HTMLTableOptions {
+      ingredient_class: bad.to_string(),
+      ..Default::default()
+    };
+    assert!(opts.validate().is_err(), \"{:?} should be rejected\", bad);
+  }
+}
+
+#[test]
+fn test_rational_overflow() {
+  use crate::quantity::Rational;
+  assert_eq!(Rational::parse(\"12345678901.123456789\"), None);
+  assert_eq!(Rational::parse(\"9223372036854775807½\"), None);
+  assert_eq!(Rational::parse(\"1.5\"), Some(Rational::new(3, 2)));
+
+  // exact whenever the answer fits
+  let big = Rational::from_integer(i64::MAX);
+  assert_eq!(big * Rational::new(1, 3) * Rational::from_integer(3), big);
+  assert_eq!(big.checked_mul(Rational::from_integer(2)), None);
+  assert_eq!(big.checked_add(Rational::from_integer(1)), None);
+  assert_eq!(big.checked_div(Rational::new(1, 2)), None);
+  assert_eq!(Rational::from_integer(1).checked_div(Rational::from_integer(0)), None);
+
+  // and rounded when it doesn't
+  assert_eq!(big * Rational::from_integer(2), big);
+  assert_eq!(big + big, big);
+  let low = Rational::from_integer(-i64::MAX);
+  assert_eq!(low + low, Rational::from_integer(i64::MIN));
+  let tiny = Rational::new(1, 999_999_937);
+  assert_eq!(tiny * tiny * tiny, Rational::from_integer(0));
+  let third = Rational::new(1, 3);
+  assert_eq!(third + tiny * tiny * Rational::new(1, 7), Rational::new(333_333, 1_000_000));
+  assert!(big * big > Rational::from_integer(0));
+  assert!(Rational::new(i64::MAX, 2) > Rational::new(i64::MAX - 1, 2));
+}
+
+// a table with a row for each of forty ingredients is far too tall
+// for an index card, so rather than shrinking it to nothing it should
+// carry on over the next few cards
+#[test]
+fn test_tall_recipe_cards() {
+  use render::card::{CardOptions, Cards, PageSize};
+  let ingredients: Vec<String> = (0..40).map(|n| format!(\"[1] thing {}\", n)).collect();
+  let source = format!(\"tall {{\\n  {} -> mix -> <>;\\n}}\", ingredients.join(\" + \"));
+  let mut s = State::new();
+  let cookbook = crate::parse_cookbook(&mut s, &source).unwrap();
+  let trees: Vec<_> = checks::Analysis::from_cookbook(&s, &cookbook)
+    .into_iter()
+    .map(|a| a.into_tree().unwrap())
+    .collect();
+  let pairs: Vec<_> = cookbook.recipes.iter().zip(trees.iter()).collect();
+  let options = CardOptions { page_size: PageSize::IndexCard, ..Default::default() };
+  let cards = Cards::new(&s, &pairs, &options).unwrap();
+  assert!(cards.page_count() >= 6, \"only {} pages\", cards.page_count());
+  cards.pdf(&mut Vec::new()).unwrap();
+}
+
+#[test]
+fn test_recipe_anchors() {
+  use render::html::{recipe_anchor, recipe_heading};
+  assert_eq!(recipe_anchor(\"chicken stock\"), \"recipe-chicken-stock\");
+  assert_eq!(recipe_anchor(\"<b>Fish & Chips</b>\"), \"recipe-b-fish-chips-b\");
+  let heading = recipe_heading(\"chicken stock\");
+  assert_eq!(heading, \"<h2 id=\\\"recipe-chicken-stock\\\">chicken stock</h2>\");
+  assert_well_formed(&heading);
+}
+
+// references only link to recipes whose headings are on the page
+#[test]
+fn test_sub_recipe_links() {
+  use render::table::{HTMLTableOptions, Table};
+  let mut s = State::new();
+  let source = \"stock { bones -> simmer -> <>; }\\nsoup { @stock -> heat -> <>; }\";
+  let cookbook = crate::parse_cookbook(&mut s, source).unwrap();
+  let analysis = checks::Analysis::from_recipe(&s, &cookbook.recipes[1]);
+  let tree = analysis.into_tree().unwrap();
+  let table = Table::new(&s, &tree);
+  let unlinked = table.html(&Default::default()).unwrap();
+  assert!(unlinked.contains(\"<span class=\\\"sub-recipe\\\">stock</span>\"));
+  let opts = HTMLTableOptions {
+    linked_recipes: vec![\"stock\".to_string()],
+    ..Default::default()
+  };
+  let linked = table.html(&opts).unwrap();
+  assert!(linked.contains(\"<a class=\\\"sub-recipe\\\" href=\\\"#recipe-stock\\\">stock</a>\"));
+  assert_well_formed(&linked);
+}
+
+// every color a theme's stylesheet uses should be one it gives a
+// value for
+#[test]
+fn test_themes() {
+  use render::theme::{Theme, THEMES};
+  for name in THEMES {
+    let theme = Theme::builtin(name).unwrap();
+    let css = theme.css();
+    for (n, _) in css.match_indices(\"var(\") {
+      let var = &css[n + 4..];
+      let var = &var[..var.find(')').unwrap()];
+      assert!(css.contains(&format!(\"{}:\", var)), \"{} doesn't set {}\", name, var);
+    }
+    assert_well_formed(&format!(\"{}</body></html>\", theme.html_header()));
+  }
+  assert!(Theme::default().css().contains(\"prefers-color-scheme: dark\"));
+  assert!(!Theme::Light.css().contains(\"prefers-color-scheme\"));
+}
 ";
 
 const TEST_TEMPLATE: &str = "
@@ -36,58 +183,318 @@ const TEST_TEMPLATE: &str = "
 fn test_%PREFIX%() {
   let source = include_str!(\"%ROOT%/tests/%PREFIX%.apicius\");
   let mut s = State::new();
-  let recipe = grammar::RecipeParser::new().parse(&mut s, source);
-  assert!(recipe.is_ok());
-  let recipe = recipe.unwrap();
+  let cookbook = crate::parse_cookbook(&mut s, source);
+  assert!(cookbook.is_ok());
+  let cookbook = cookbook.unwrap();
 
   let mut buf = Vec::new();
-  s.debug_recipe(&mut buf, &recipe).unwrap();
+  s.debug_cookbook(&mut buf, &cookbook).unwrap();
   assert_eq(
     std::str::from_utf8(&buf).unwrap().trim(),
     include_str!(\"%ROOT%/tests/%PREFIX%.exp\").trim(),
   );
 
-  let analysis = checks::Analysis::from_recipe(&s, &recipe);
-  assert_eq(
-    &format!(\"{:#?}\", Printable {
+  let quantities_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.quantities\");
+  if quantities_path.exists() {
+    let expected = std::fs::read_to_string(quantities_path).unwrap();
+    let mut buf = Vec::new();
+    s.debug_quantities(&mut buf).unwrap();
+    assert_eq(std::str::from_utf8(&buf).unwrap().trim(), expected.trim());
+  }
+
+  let servings_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.servings\");
+  if servings_path.exists() {
+    let expected = std::fs::read_to_string(servings_path).unwrap();
+    let mut printed = String::new();
+    for recipe in cookbook.recipes.iter() {
+      match crate::scale::servings(&s, recipe) {
+        Some(n) => printed.push_str(&format!(\"{}: {}\\n\", &s[recipe.name], n)),
+        None => printed.push_str(&format!(\"{}: unknown\\n\", &s[recipe.name])),
+      }
+    }
+    assert_eq(printed.trim(), expected.trim());
+  }
+
+  // scaling changes the amounts in the state, so it gets its own copy
+  // of the recipes to work on
+  let scaled_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.scaled\");
+  if scaled_path.exists() {
+    let expected = std::fs::read_to_string(scaled_path).unwrap();
+    let mut s = State::new();
+    let mut cookbook = crate::parse_cookbook(&mut s, source).unwrap();
+    let mut warnings = String::new();
+    for recipe in cookbook.recipes.iter_mut() {
+      for w in crate::scale::scale(&mut s, recipe, crate::quantity::Rational::new(3, 2)) {
+        warnings.push_str(&w.render(source));
+      }
+    }
+    let mut buf = Vec::new();
+    s.debug_cookbook(&mut buf, &cookbook).unwrap();
+    let printed = format!(\"{}\\n{}\", std::str::from_utf8(&buf).unwrap(), warnings);
+    assert_eq(printed.trim(), expected.trim());
+  }
+
+  let units_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.units\");
+  if units_path.exists() {
+    let expected = std::fs::read_to_string(units_path).unwrap();
+    let densities_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.densities\");
+    let densities = if densities_path.exists() {
+      crate::units::DensityTable::parse(&std::fs::read_to_string(densities_path).unwrap()).unwrap()
+    } else {
+      crate::units::DensityTable::new()
+    };
+    let mut printed = String::new();
+    for (name, system) in [(\"metric\", crate::units::System::Metric), (\"us\", crate::units::System::Us)] {
+      let mut s = State::new();
+      let cookbook = crate::parse_cookbook(&mut s, source).unwrap();
+      for recipe in cookbook.recipes.iter() {
+        crate::units::convert_recipe(&mut s, recipe, system, &densities);
+      }
+      let mut buf = Vec::new();
+      s.debug_cookbook(&mut buf, &cookbook).unwrap();
+      printed.push_str(&format!(\"# {}\\n{}\\n\", name, std::str::from_utf8(&buf).unwrap()));
+    }
+    assert_eq(printed.trim(), expected.trim());
+  }
+
+  let dot_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.dot\");
+  if dot_path.exists() {
+    let expected = std::fs::read_to_string(dot_path).unwrap();
+    let all = checks::Analysis::from_cookbook(&s, &cookbook);
+    let refs: Vec<&checks::Analysis> = all.iter().collect();
+    assert_eq(render::dot::dot(&s, &refs).trim(), expected.trim());
+  }
+
+  // every other expectation covers each recipe in the file in turn
+  let mut analyses = Vec::new();
+  let mut problems = Vec::new();
+  let mut diagnostics = Vec::new();
+  let mut trees = Vec::new();
+  for analysis in checks::Analysis::from_cookbook(&s, &cookbook) {
+    analyses.push(format!(\"{:#?}\", Printable {
       value: &analysis,
       state: &s,
-    }).trim(),
+    }));
+
+    let mut buf = Vec::new();
+    analysis.debug_problems(&mut buf, &s).unwrap();
+    problems.push(String::from_utf8(buf).unwrap());
+
+    diagnostics.extend(analysis.diagnostics(&s).iter().map(|d| d.render(source)));
+    trees.push(analysis.into_tree());
+  }
+
+  assert_eq(
+    analyses.join(\"\\n\").trim(),
     include_str!(\"%ROOT%/tests/%PREFIX%.analysis\").trim(),
   );
 
-  let mut buf = Vec::new();
-  analysis.debug_problems(&mut buf, &s).unwrap();
   assert_eq(
-    std::str::from_utf8(&buf).unwrap().trim(),
+    problems.join(\"\").trim(),
     include_str!(\"%ROOT%/tests/%PREFIX%.problems\").trim(),
   );
 
+  let diag_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.diagnostics\");
+  if diag_path.exists() {
+    let expected = std::fs::read_to_string(diag_path).unwrap();
+    assert_eq(diagnostics.join(\"\\n\").trim(), expected.trim());
+  }
+
   let bt_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.backward_tree\");
   if bt_path.exists() {
     let expected = std::fs::read_to_string(bt_path).unwrap();
-    let tree = analysis.into_tree().expect(\"Had a valid backward tree test but cannot build a tree\");
+    let trees: Vec<checks::BackwardTree> = trees
+      .into_iter()
+      .map(|t| t.expect(\"Had a valid backward tree test but cannot build a tree\"))
+      .collect();
+    let printed: Vec<String> = trees.iter().map(|tree| format!(\"{:#?}\", Printable {
+      value: tree,
+      state: &s,
+    })).collect();
     assert_eq(
-      &format!(\"{:#?}\", Printable {
-        value: &tree,
-        state: &s,
-      }),
+      &printed.join(\"\\n\"),
       expected.trim(),
     );
 
+    let inline_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.inlined_tree\");
+    if inline_path.exists() {
+      let expected = std::fs::read_to_string(inline_path).unwrap();
+      let by_name: std::collections::BTreeMap<_, _> = cookbook.recipes.iter().map(|r| r.name.value).zip(trees.iter().cloned()).collect();
+      let printed: Vec<String> = trees.iter().map(|tree| format!(\"{:#?}\", Printable {
+        value: &tree.inline_sub_recipes(&s, &by_name),
+        state: &s,
+      })).collect();
+      assert_eq(
+        &printed.join(\"\\n\"),
+        expected.trim(),
+      );
+    }
+
+    let timing_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.timing\");
+    if timing_path.exists() {
+      let expected = std::fs::read_to_string(timing_path).unwrap();
+      let mut buf = Vec::new();
+      for (recipe, tree) in cookbook.recipes.iter().zip(trees.iter()) {
+        for w in crate::timing::unreadable_times(&s, tree) {
+          buf.extend(w.render(source).bytes());
+        }
+        buf.extend(format!(\"{}:\\n\", &s[recipe.name]).bytes());
+        crate::timing::Timing::from_tree(tree).debug(&mut buf, &s).unwrap();
+      }
+      assert_eq(std::str::from_utf8(&buf).unwrap().trim(), expected.trim());
+    }
+
+    let schedule_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.schedule\");
+    if schedule_path.exists() {
+      let expected = std::fs::read_to_string(schedule_path).unwrap();
+      let mut buf = String::new();
+      for (recipe, tree) in cookbook.recipes.iter().zip(trees.iter()) {
+        buf.push_str(&format!(\"{}:\\n\", &s[recipe.name]));
+        buf.push_str(&crate::schedule::Schedule::new(tree, 2).text(&s));
+      }
+      assert_eq(buf.trim(), expected.trim());
+    }
+
+    for (ext, svg) in [(\"gantt_svg\", true), (\"gantt_html\", false)] {
+      let path = std::path::Path::new(\"%ROOT%/tests\").join(format!(\"%PREFIX%.{}\", ext));
+      if path.exists() {
+        let expected = std::fs::read_to_string(path).unwrap();
+        let charts: Vec<String> = trees.iter().map(|tree| {
+          let plan = crate::schedule::Schedule::new(tree, 2);
+          let gantt = render::gantt::Gantt::new(&s, &plan);
+          if svg { gantt.svg() } else { gantt.html() }
+        }).collect();
+        assert_eq(charts.join(\"\\n\").trim(), expected.trim());
+      }
+    }
+
+    // the graph depends on what fonts are around, so we can't check
+    // exactly what it looks like, but we can at least check it draws
+    for tree in trees.iter() {
+      let graph = render::graph::layout(&s, tree, &Default::default()).unwrap();
+      let mut png = Vec::new();
+      graph.png(&mut png).unwrap();
+      assert!(png.starts_with(b\"\\x89PNG\"), \"the graph should be a PNG\");
+      let mut svg = Vec::new();
+      graph.svg(&mut svg).unwrap();
+      assert!(String::from_utf8_lossy(&svg).contains(\"<svg\"), \"the graph should be an SVG\");
+    }
+    let pairs: Vec<_> = cookbook.recipes.iter().zip(trees.iter()).collect();
+    let cards = render::card::Cards::new(&s, &pairs, &Default::default()).unwrap();
+    cards.pdf(&mut Vec::new()).unwrap();
+
+    let shopping_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.shopping_list\");
+    if shopping_path.exists() {
+      let expected = std::fs::read_to_string(shopping_path).unwrap();
+      let by_name: std::collections::BTreeMap<_, _> = cookbook.recipes.iter()
+        .map(|r| r.name.value)
+        .zip(trees.iter().cloned())
+        .collect();
+      let all: Vec<_> = cookbook.recipes.iter().collect();
+      let mut buf = crate::shopping::ShoppingList::for_recipes(&s, &cookbook, &by_name, &all).text();
+      for recipe in all.iter() {
+        if !recipe.sub_recipes(&s).is_empty() {
+          let list = crate::shopping::ShoppingList::for_recipes(&s, &cookbook, &by_name, &[recipe]);
+          buf.push_str(&format!(\"\\n{}:\\n{}\", &s[recipe.name], list.text()));
+        }
+      }
+      assert_eq(buf.trim(), expected.trim());
+    }
+
+    let instructions_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.instructions\");
+    if instructions_path.exists() {
+      let expected = std::fs::read_to_string(instructions_path).unwrap();
+      let mut buf = String::new();
+      for (recipe, tree) in cookbook.recipes.iter().zip(trees.iter()) {
+        buf.push_str(&format!(\"{}:\\n\", &s[recipe.name]));
+        buf.push_str(&render::instructions::Instructions::new(&s, tree).text());
+      }
+      assert_eq(buf.trim(), expected.trim());
+    }
+
+    let latex_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.latex\");
+    if latex_path.exists() {
+      let expected = std::fs::read_to_string(latex_path).unwrap();
+      let opts = render::latex::LatexOptions { standalone: false, graph: true };
+      assert_eq(render::latex::latex(&s, &pairs, &opts).trim(), expected.trim());
+    }
+
+    let markdown_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.markdown\");
+    if markdown_path.exists() {
+      let expected = std::fs::read_to_string(markdown_path).unwrap();
+      let document = render::markdown::markdown(&s, &pairs, &Default::default());
+      assert_eq(document.trim(), expected.trim());
+    }
+
+    let html_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.html\");
+    if html_path.exists() {
+      let expected = std::fs::read_to_string(html_path).unwrap();
+      let tables: Vec<String> = trees
+        .iter()
+        .map(|tree| render::table::Table::new(&s, tree).html(&Default::default()).unwrap())
+        .collect();
+      assert_eq(tables.join(\"\\n\").trim(), expected.trim());
+    }
+
+    // whatever's in the recipe, the HTML we write has to hold together
+    for tree in trees.iter() {
+      let table = render::table::Table::new(&s, tree).html(&Default::default()).unwrap();
+      assert_well_formed(&table);
+      assert_well_formed(&render::instructions::Instructions::new(&s, tree).html());
+      let plan = crate::schedule::Schedule::new(tree, 2);
+      assert_well_formed(&render::gantt::Gantt::new(&s, &plan).html());
+    }
+
+    let mermaid_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.mermaid\");
+    if mermaid_path.exists() {
+      let expected = std::fs::read_to_string(mermaid_path).unwrap();
+      let chart = render::mermaid::mermaid(&s, &pairs, &Default::default());
+      assert_eq(chart.trim(), expected.trim());
+    }
+
     let table_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.raw_table\");
     if table_path.exists() {
       let expected = std::fs::read_to_string(table_path).unwrap();
-      let table = render::table::Table::new(&s, &tree);
+      let tables: Vec<String> = trees.iter().map(|tree| render::table::Table::new(&s, tree).debug()).collect();
+      for tree in trees.iter() {
+        let drawing = render::table::Table::new(&s, tree).drawing(&Default::default()).unwrap();
+        let mut svg = Vec::new();
+        drawing.svg(&mut svg).unwrap();
+        assert!(String::from_utf8_lossy(&svg).contains(\"<svg\"), \"the table should be an SVG\");
+      }
       assert_eq(
-        table.debug().trim(),
+        tables.join(\"\\n\").trim(),
         expected.trim(),
       );
     }
+
+    let text_path = std::path::Path::new(\"%ROOT%/tests/%PREFIX%.text_table\");
+    if text_path.exists() {
+      let expected = std::fs::read_to_string(text_path).unwrap();
+      let tables: Vec<String> = trees
+        .iter()
+        .map(|tree| render::table::Table::new(&s, tree).text(&Default::default()))
+        .collect();
+      assert_eq(tables.join(\"\\n\").trim(), expected.trim());
+    }
   }
 }
 ";
 
+const PARSE_ERROR_TEMPLATE: &str = "
+// test for %FILE%
+#[test]
+fn test_%PREFIX%() {
+  let source = include_str!(\"%ROOT%/tests/%PREFIX%.apicius\");
+  let mut s = State::new();
+  let err = crate::parse_cookbook(&mut s, source).expect_err(\"Had a parse error test but parsing succeeded\");
+  assert_eq(
+    err.render(source).trim(),
+    include_str!(\"%ROOT%/tests/%PREFIX%.parse_error\").trim(),
+  );
+}
+";
+
 fn main() {
     lalrpop::process_root().unwrap();
 
@@ -101,7 +508,12 @@ fn main() {
         let exp = exp.unwrap().path().canonicalize().unwrap();
         let fname = exp.file_name().unwrap().to_string_lossy();
         if let Some(prefix) = fname.strip_suffix(".apicius") {
-            let test = TEST_TEMPLATE
+            let template = if exp.with_extension("parse_error").exists() {
+                PARSE_ERROR_TEMPLATE
+            } else {
+                TEST_TEMPLATE
+            };
+            let test = template
                 .replace("%FILE%", &fname)
                 .replace("%PREFIX%", prefix)
                 .replace("%ROOT%", &manifest_dir);
@@ -5,13 +5,62 @@ use std::cmp::max;
 use std::collections::{BTreeMap, BTreeSet};
 use std::{fmt, io};
 
+use crate::diagnostics::{Diagnostic, Label};
 pub use crate::types::State;
 use crate::types::*;
 
+/// The source region covered by an ingredient, including its amount
+fn ingredient_span(state: &State, i: IngredientRef) -> (usize, usize) {
+    let i = &state[i];
+    (i.amount.map_or(i.stuff.l, |amt| amt.l), i.stuff.r)
+}
+
+/// The source region covered by a step, including its seasonings
+fn step_span(state: &State, step: &ActionStep) -> (usize, usize) {
+    let r = step
+        .seasonings
+        .iter()
+        .map(|i| ingredient_span(state, *i).1)
+        .fold(step.action.r, max);
+    (step.action.l, r)
+}
+
+/// The source region covered by an action in a rule
+fn action_span(state: &State, action: &Action) -> (usize, usize) {
+    match action {
+        Action::Action { step } => step_span(state, step),
+        Action::Join { point } => (point.l, point.r),
+        Action::Done { at } => (at.l, at.r),
+    }
+}
+
+/// Describe a `Problem::Disconnected`
+fn disconnected_message(state: &State, points: &[StringRef]) -> String {
+    if let [point] = points {
+        return format!("the join point '{}' never leads to `<>`", &state[*point]);
+    }
+    let names: Vec<String> = points.iter().map(|p| format!("'{}'", &state[*p])).collect();
+    format!("the join points {} never lead to `<>`", names.join(", "))
+}
+
+/// The source region covered by the input to a rule
+fn input_span(state: &State, input: &Input) -> (usize, usize) {
+    match input {
+        Input::Join { point } => (point.l, point.r),
+        Input::Ingredients { list } => (
+            ingredient_span(state, list[0]).0,
+            ingredient_span(state, list[list.len() - 1]).1,
+        ),
+    }
+}
+
 #[derive(Debug)]
-struct Path {
-    actions: Vec<ActionStep>,
-    start: Input,
+pub(crate) struct Path {
+    pub(crate) actions: Vec<ActionStep>,
+    pub(crate) start: Input,
+    // the join point this path leads into, or `None` if it ends in
+    // `<>` (or doesn't end anywhere at all)
+    pub(crate) end: Option<StringRef>,
 }
 
 #[derive(Debug)]
@@ -31,26 +80,68 @@ impl fmt::Display for Problems {
 
 impl std::error::Error for Problems {}
 
+/// One step around a cycle: a path which starts at the join point
+/// `from` and leads into the join point `to`
+#[derive(Debug, Clone)]
+pub struct CycleLink {
+    pub from: StringRef,
+    pub actions: Vec<ActionStep>,
+    pub to: StringRef,
+}
+
 /// A `Problem` represents an invariant failure which would prevent
 /// our rendering code from rending a recipe.
-///
-/// TODO:
-///  - Better display of cycles
-///  - Discovering disconnected parts of the graph
 #[derive(Debug)]
 pub enum Problem {
     /// Every recipe needs a `<>` so we can work backwards from it, so
-    /// it's an error to omit the `<>` value
-    NoDone,
+    /// it's an error to omit the `<>` value. This holds the name of
+    /// the recipe.
+    NoDone(StringRef),
     /// If the last thing in a sequence of actions is not a join point
     /// or `<>`, then those actions are effectively useless
-    ///
-    /// TODO: detect this after `<>` and not just after join points
     DanglingSteps(Vec<ActionStep>, Input),
+    /// The `<>` means the recipe is finished, so nothing can come
+    /// after it in a rule. This holds the `<>` and everything after
+    /// it.
+    StepsAfterDone(Loc<()>, Vec<Action>),
+    /// Similarly, a single rule can't finish the recipe more than
+    /// once. This holds every `<>` in the rule, and comes along with a
+    /// `StepsAfterDone` for everything after the first one.
+    MultipleDone(Vec<Loc<()>>),
     /// We want our recipes to be strictly tree-shaped, so disallow
     /// any cycles. We might lift this restriction in the future, but
-    /// it's a _huge_ simplifying assumption for recipe graphing.
-    HasCycle(string_interner::DefaultSymbol),
+    /// it's a _huge_ simplifying assumption for recipe graphing. This
+    /// holds every link in the cycle, in order, so the last link
+    /// leads back into the join point the first one starts from.
+    HasCycle(Vec<CycleLink>),
+    /// A join point which a path starts from but which no path ever
+    /// leads into. This holds every place it's used.
+    UndefinedJoinPoint(Vec<StringRef>),
+    /// A join point which paths lead into but which no path ever
+    /// starts from, so everything leading into it is wasted. This
+    /// holds every place it's introduced.
+    UnusedJoinPoint(Vec<StringRef>),
+    /// Since recipes are trees, a join point can only be the start of
+    /// a single path: otherwise we'd have to draw the same preparation
+    /// in more than one place. This holds every place it's used.
+    ReusedJoinPoint(Vec<StringRef>),
+    /// A group of join points which are connected to each other but
+    /// never lead to `<>`, and which aren't already explained by an
+    /// unused join point or dangling steps. This holds the first
+    /// place each join point is introduced.
+    Disconnected(Vec<StringRef>),
+    /// An `@name` ingredient which doesn't name any recipe in the
+    /// cookbook
+    UnknownRecipe(StringRef),
+    /// A recipe which uses itself as an ingredient, possibly by way
+    /// of other recipes. This holds each `@name` reference in order,
+    /// starting in this recipe and ending with the reference back to
+    /// it.
+    RecipeCycle(Vec<StringRef>),
+    /// A recipe with the same name as an earlier one in the cookbook,
+    /// which `@name` could never refer to. This holds the name of the
+    /// earlier recipe and then of this one.
+    DuplicateRecipe(StringRef, StringRef),
 }
 
 /// An `Analysis` takes the raw recipe and turns it into an abstract
@@ -91,8 +182,9 @@ pub enum Problem {
 /// ```
 #[derive(Debug)]
 pub struct Analysis {
-    map: BTreeMap<Option<string_interner::DefaultSymbol>, Vec<Path>>,
-    problems: Vec<Problem>,
+    pub(crate) name: StringRef,
+    pub(crate) map: BTreeMap<Option<string_interner::DefaultSymbol>, Vec<Path>>,
+    pub(crate) problems: Vec<Problem>,
 }
 
 /// The "backwards" version of a recipe starting from the end,
@@ -149,7 +241,7 @@ pub struct Analysis {
 ///   ingredients: [three]
 ///   actions: [quux]
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BackwardTree {
     pub actions: Vec<ActionStep>,
     pub paths: Vec<BackwardTree>,
@@ -162,10 +254,7 @@ impl Analysis {
     /// Add a new `Path` that leads to a given join point (or the root
     /// for `None`)
     fn add(&mut self, key: Option<StringRef>, value: Path) {
-        self.map
-            .entry(key.map(|x| *x))
-            .or_insert_with(Vec::new)
-            .push(value);
+        self.map.entry(key.map(|x| *x)).or_default().push(value);
     }
 
     /// Print the list of `Problem` values for this `Analysis` to the
@@ -178,7 +267,7 @@ impl Analysis {
             for p in self.problems.iter() {
                 write!(w, " - ")?;
                 match p {
-                    Problem::NoDone => write!(w, "no `<>` state")?,
+                    Problem::NoDone(_) => write!(w, "no `<>` state")?,
                     Problem::DanglingSteps(actions, Input::Ingredients { list }) => {
                         write!(w, "path starting from ingredients list '")?;
                         state.debug_ingredients(w, list)?;
@@ -197,11 +286,61 @@ impl Analysis {
                         }
                         write!(w, "...' but never reaches a join point")?;
                     }
-                    Problem::HasCycle(sym) => write!(
+                    Problem::StepsAfterDone(_, rest) => {
+                        write!(w, "path continues after `<>` with '")?;
+                        for (i, a) in rest.iter().enumerate() {
+                            if i > 0 {
+                                write!(w, " -> ")?;
+                            }
+                            state.debug_action(w, a)?;
+                        }
+                        write!(w, "'")?;
+                    }
+                    Problem::MultipleDone(dones) => {
+                        write!(w, "path reaches `<>` {} times", dones.len())?
+                    }
+                    Problem::HasCycle(links) => {
+                        write!(
+                            w,
+                            "the join points form a cycle: '{}'",
+                            &state[links[0].from]
+                        )?;
+                        for link in links.iter() {
+                            for a in link.actions.iter() {
+                                write!(w, " -> ")?;
+                                state.debug_action_step(w, a)?;
+                            }
+                            write!(w, " -> '{}'", &state[link.to])?;
+                        }
+                    }
+                    Problem::UndefinedJoinPoint(points) => write!(
+                        w,
+                        "the join point '{}' is used but never introduced",
+                        &state[points[0]]
+                    )?,
+                    Problem::UnusedJoinPoint(points) => write!(
                         w,
-                        "the join point '{}' is involved in a cycle",
-                        &state[*sym]
+                        "the join point '{}' is introduced but never used",
+                        &state[points[0]]
                     )?,
+                    Problem::ReusedJoinPoint(points) => write!(
+                        w,
+                        "the join point '{}' is used in {} different places",
+                        &state[points[0]],
+                        points.len()
+                    )?,
+                    Problem::Disconnected(points) => {
+                        write!(w, "{}", disconnected_message(state, points))?
+                    }
+                    Problem::UnknownRecipe(name) => {
+                        write!(w, "there is no recipe called '{}'", &state[*name])?
+                    }
+                    Problem::RecipeCycle(refs) => {
+                        write!(w, "{}", self.recipe_cycle_message(state, refs))?
+                    }
+                    Problem::DuplicateRecipe(_, name) => {
+                        write!(w, "there is already a recipe called '{}'", &state[*name])?
+                    }
                 }
                 writeln!(w)?;
             }
@@ -210,34 +349,467 @@ impl Analysis {
         Ok(())
     }
 
-    /// Find all cycles in the graph.
-    /// TODO: also find disconnected components here
-    /// TODO: print more of the cycle to make it easier to diagnose,
-    /// instead of just, "Hey, here's a node that's involved in a
-    /// cycle."
-    fn find_cycles(&mut self) {
-        // this is just doing DFS with an explicit stack
-        let mut frontier: Vec<string_interner::DefaultSymbol> = Vec::new();
-        let mut seen = BTreeSet::new();
+    /// Produce a `Diagnostic` for each `Problem` in this `Analysis`,
+    /// pointing at the parts of the source responsible for it
+    pub fn diagnostics(&self, state: &State) -> Vec<Diagnostic> {
+        self.problems
+            .iter()
+            .map(|p| self.diagnostic(state, p))
+            .collect()
+    }
 
-        for path in self.map[&None].iter() {
+    fn diagnostic(&self, state: &State, problem: &Problem) -> Diagnostic {
+        match problem {
+            Problem::NoDone(name) => {
+                Diagnostic::new(format!("recipe '{}' never reaches `<>`", &state[*name]))
+                    .with_label(Label::primary(
+                        name.l,
+                        name.r,
+                        "no rule in this recipe ends with `<>`",
+                    ))
+            }
+            Problem::DanglingSteps(actions, start) => {
+                let (l, _) = step_span(state, &actions[0]);
+                let (_, r) = step_span(state, &actions[actions.len() - 1]);
+                let mut diagnostic =
+                    Diagnostic::new("these steps never reach a join point or `<>`")
+                        .with_label(Label::primary(l, r, "these steps don't lead anywhere"));
+                match start {
+                    Input::Join { point } => {
+                        // a rule that continues on through a join point
+                        // both introduces and uses it at the same spot
+                        for intro in self
+                            .introductions(point.value)
+                            .into_iter()
+                            .filter(|intro| intro != point)
+                        {
+                            diagnostic = diagnostic.with_label(Label::secondary(
+                                intro.l,
+                                intro.r,
+                                format!("'{}' is introduced here", &state[*point]),
+                            ));
+                        }
+                        diagnostic.with_label(Label::secondary(
+                            point.l,
+                            point.r,
+                            "starting from this join point",
+                        ))
+                    }
+                    Input::Ingredients { .. } => {
+                        let (l, r) = input_span(state, start);
+                        diagnostic.with_label(Label::secondary(
+                            l,
+                            r,
+                            "starting from these ingredients",
+                        ))
+                    }
+                }
+            }
+            Problem::StepsAfterDone(at, rest) => {
+                let (_, r) = action_span(state, &rest[rest.len() - 1]);
+                Diagnostic::new("a path can't continue after `<>`")
+                    .with_label(Label::secondary(at.l, at.r, "the recipe is finished here"))
+                    .with_label(Label::primary(
+                        action_span(state, &rest[0]).0,
+                        r,
+                        "so these steps will never happen",
+                    ))
+            }
+            Problem::MultipleDone(dones) => {
+                let mut diagnostic = Diagnostic::new("a path can only reach `<>` once");
+                for done in dones.iter() {
+                    diagnostic = diagnostic.with_label(Label::primary(
+                        done.l,
+                        done.r,
+                        "the recipe is finished here",
+                    ));
+                }
+                diagnostic
+            }
+            Problem::HasCycle(links) => {
+                let names: Vec<String> = links
+                    .iter()
+                    .map(|link| format!("'{}'", &state[link.from]))
+                    .collect();
+                let mut diagnostic = Diagnostic::new(format!(
+                    "the join points form a cycle: {} -> '{}'",
+                    names.join(" -> "),
+                    &state[links[0].from]
+                ));
+                for link in links.iter() {
+                    diagnostic = diagnostic.with_label(Label::primary(
+                        link.from.l,
+                        link.to.r,
+                        format!("'{}' leads into '{}'", &state[link.from], &state[link.to]),
+                    ));
+                }
+                diagnostic
+            }
+            Problem::UndefinedJoinPoint(points) => {
+                let mut diagnostic = Diagnostic::new(format!(
+                    "the join point '{}' is used but never introduced",
+                    &state[points[0]]
+                ));
+                for point in points.iter() {
+                    diagnostic = diagnostic.with_label(Label::primary(
+                        point.l,
+                        point.r,
+                        "nothing leads into this join point",
+                    ));
+                }
+                diagnostic
+            }
+            Problem::UnusedJoinPoint(points) => {
+                let mut diagnostic = Diagnostic::new(format!(
+                    "the join point '{}' is introduced but never used",
+                    &state[points[0]]
+                ));
+                for point in points.iter() {
+                    diagnostic = diagnostic.with_label(Label::primary(
+                        point.l,
+                        point.r,
+                        "nothing starts from this join point",
+                    ));
+                }
+                diagnostic
+            }
+            Problem::ReusedJoinPoint(points) => {
+                let mut diagnostic = Diagnostic::new(format!(
+                    "the join point '{}' is used in more than one place",
+                    &state[points[0]]
+                ));
+                for point in points.iter() {
+                    diagnostic = diagnostic.with_label(Label::primary(
+                        point.l,
+                        point.r,
+                        format!("'{}' is used here", &state[*point]),
+                    ));
+                }
+                diagnostic
+            }
+            Problem::Disconnected(points) => {
+                let mut diagnostic = Diagnostic::new(disconnected_message(state, points));
+                for point in points.iter() {
+                    diagnostic = diagnostic.with_label(Label::primary(
+                        point.l,
+                        point.r,
+                        format!("'{}' is introduced here", &state[*point]),
+                    ));
+                }
+                diagnostic
+            }
+            Problem::UnknownRecipe(name) => {
+                Diagnostic::new(format!("there is no recipe called '{}'", &state[*name]))
+                    .with_label(Label::primary(
+                        name.l,
+                        name.r,
+                        "this refers to a recipe that doesn't exist",
+                    ))
+            }
+            Problem::RecipeCycle(refs) => {
+                let mut diagnostic = Diagnostic::new(self.recipe_cycle_message(state, refs));
+                for r in refs.iter() {
+                    diagnostic = diagnostic.with_label(Label::primary(
+                        r.l,
+                        r.r,
+                        format!("'{}' is used here", &state[*r]),
+                    ));
+                }
+                diagnostic
+            }
+            Problem::DuplicateRecipe(first, name) => Diagnostic::new(format!(
+                "there is already a recipe called '{}'",
+                &state[*name]
+            ))
+            .with_label(Label::secondary(first.l, first.r, "the first one is here"))
+            .with_label(Label::primary(
+                name.l,
+                name.r,
+                "so this one can't be told apart from it",
+            )),
+        }
+    }
+
+    fn recipe_cycle_message(&self, state: &State, refs: &[StringRef]) -> String {
+        let mut names = vec![format!("'{}'", &state[self.name])];
+        names.extend(refs.iter().map(|r| format!("'{}'", &state[*r])));
+        format!("the recipes form a cycle: {}", names.join(" -> "))
+    }
+
+    /// Find every place where a join point appears as the end of a
+    /// path
+    fn introductions(&self, sym: string_interner::DefaultSymbol) -> Vec<StringRef> {
+        self.map
+            .get(&Some(sym))
+            .into_iter()
+            .flatten()
+            .filter_map(|path| path.end)
+            .collect()
+    }
+
+    /// Find every cycle in the graph.
+    ///
+    /// We treat each join point as a node and each path which starts
+    /// at one join point and leads into another as an edge, and then
+    /// do a depth-first search from every join point. Any time we
+    /// find an edge back to a join point that's still on the stack,
+    /// the edges on the stack from that join point onwards form a
+    /// cycle.
+    fn find_cycles(&mut self) {
+        let mut edges: BTreeMap<string_interner::DefaultSymbol, Vec<CycleLink>> = BTreeMap::new();
+        for path in self.map.values().flatten() {
             if let Input::Join { point } = path.start {
-                frontier.push(point.value)
+                if let Some(end) = path.end {
+                    edges.entry(point.value).or_default().push(CycleLink {
+                        from: point,
+                        actions: path.actions.clone(),
+                        to: end,
+                    });
+                }
+            }
+        }
+
+        let mut finished = BTreeSet::new();
+        let mut cycles = Vec::new();
+        for node in edges.keys() {
+            Analysis::cycle_search(
+                &edges,
+                &mut vec![*node],
+                &mut Vec::new(),
+                &mut finished,
+                &mut cycles,
+            );
+        }
+
+        for cycle in cycles.into_iter() {
+            self.problems.push(Problem::HasCycle(cycle));
+        }
+    }
+
+    /// Find join points which are used but never introduced (or vice
+    /// versa) or used more than once, as well as groups of join points
+    /// which never make it to the `<>` at all.
+    fn find_disconnected(&mut self) {
+        let mut usages: BTreeMap<string_interner::DefaultSymbol, Vec<StringRef>> = BTreeMap::new();
+        let starts = self.map.values().flatten().map(|path| &path.start);
+        // paths with dangling steps never made it into the map, but
+        // they still count as using their join point
+        let dangling: Vec<&Input> = self
+            .problems
+            .iter()
+            .filter_map(|p| match p {
+                Problem::DanglingSteps(_, start) => Some(start),
+                _ => None,
+            })
+            .collect();
+        // and if the steps after a join point go nowhere, that already
+        // explains why it never leads to `<>`
+        let mut explained = BTreeSet::new();
+        for start in dangling.iter() {
+            if let Input::Join { point } = start {
+                explained.insert(point.value);
+            }
+        }
+        for start in starts.chain(dangling) {
+            if let Input::Join { point } = start {
+                usages.entry(point.value).or_default().push(*point);
             }
         }
 
-        while let Some(elem) = frontier.pop() {
-            if seen.contains(&elem) {
-                self.problems.push(Problem::HasCycle(elem));
-                break;
+        for (key, paths) in self.map.iter() {
+            if let Some(sym) = key {
+                if !usages.contains_key(sym) {
+                    explained.insert(*sym);
+                    let mut points: Vec<StringRef> =
+                        paths.iter().filter_map(|path| path.end).collect();
+                    points.sort();
+                    self.problems.push(Problem::UnusedJoinPoint(points));
+                }
             }
-            seen.insert(elem);
-            for path in self.map[&Some(elem)].iter() {
+        }
+        for (sym, mut points) in usages.into_iter() {
+            points.sort();
+            if !self.map.contains_key(&Some(sym)) {
+                self.problems.push(Problem::UndefinedJoinPoint(points));
+            } else if points.len() > 1 {
+                self.problems.push(Problem::ReusedJoinPoint(points));
+            }
+        }
+
+        // without a `<>` nothing is reachable, and we've already
+        // complained about that
+        if !self.map.contains_key(&None) {
+            return;
+        }
+
+        // walk backwards from the `<>` to find every join point that
+        // eventually leads to it
+        let mut reachable = BTreeSet::new();
+        let mut frontier = vec![None];
+        while let Some(key) = frontier.pop() {
+            for path in self.map.get(&key).into_iter().flatten() {
                 if let Input::Join { point } = path.start {
-                    frontier.push(point.value);
+                    if reachable.insert(point.value) {
+                        frontier.push(Some(point.value));
+                    }
                 }
             }
         }
+
+        // group everything else into connected components, so that
+        // each disconnected sub-graph only gets reported once
+        let mut components: Vec<BTreeSet<string_interner::DefaultSymbol>> = Vec::new();
+        for (key, paths) in self.map.iter() {
+            let sym = match key {
+                Some(sym) if !reachable.contains(sym) => *sym,
+                _ => continue,
+            };
+            let mut component: BTreeSet<_> = paths
+                .iter()
+                .filter_map(|path| match path.start {
+                    Input::Join { point } if self.map.contains_key(&Some(point.value)) => {
+                        Some(point.value)
+                    }
+                    _ => None,
+                })
+                .collect();
+            component.insert(sym);
+            let (overlapping, mut rest): (Vec<_>, Vec<_>) = components
+                .into_iter()
+                .partition(|c| !c.is_disjoint(&component));
+            for c in overlapping.into_iter() {
+                component.extend(c);
+            }
+            rest.push(component);
+            components = rest;
+        }
+
+        for component in components.into_iter() {
+            if !component.is_disjoint(&explained) {
+                continue;
+            }
+            let mut points: Vec<StringRef> = component
+                .iter()
+                .filter_map(|sym| self.introductions(*sym).into_iter().min())
+                .collect();
+            points.sort();
+            self.problems.push(Problem::Disconnected(points));
+        }
+    }
+
+    /// The recursive part of `find_cycles`. The invariant here is that
+    /// `links[i]` goes from `nodes[i]` to `nodes[i + 1]`, and the last
+    /// element of `nodes` is the one we're currently exploring.
+    fn cycle_search<'a>(
+        edges: &'a BTreeMap<string_interner::DefaultSymbol, Vec<CycleLink>>,
+        nodes: &mut Vec<string_interner::DefaultSymbol>,
+        links: &mut Vec<&'a CycleLink>,
+        finished: &mut BTreeSet<string_interner::DefaultSymbol>,
+        cycles: &mut Vec<Vec<CycleLink>>,
+    ) {
+        let node = nodes[nodes.len() - 1];
+        if finished.contains(&node) {
+            return;
+        }
+        for link in edges.get(&node).into_iter().flatten() {
+            let next = link.to.value;
+            if let Some(pos) = nodes.iter().position(|n| *n == next) {
+                let mut cycle: Vec<CycleLink> = links[pos..].iter().map(|l| (*l).clone()).collect();
+                cycle.push(link.clone());
+                cycles.push(cycle);
+            } else {
+                nodes.push(next);
+                links.push(link);
+                Analysis::cycle_search(edges, nodes, links, finished, cycles);
+                nodes.pop();
+                links.pop();
+            }
+        }
+        finished.insert(node);
+    }
+
+    /// Analyze every recipe in a `Cookbook`. On top of the checks
+    /// that `from_recipe` does, this also finds recipes with the same
+    /// name as an earlier one, `@name` references to recipes that
+    /// don't exist and recipes which end up using themselves as an
+    /// ingredient. The resulting `Analysis` values
+    /// are in the same order as the recipes in the cookbook.
+    pub fn from_cookbook(state: &State, cookbook: &Cookbook) -> Vec<Analysis> {
+        let mut analyses: Vec<Analysis> = cookbook
+            .recipes
+            .iter()
+            .map(|recipe| Analysis::from_recipe(state, recipe))
+            .collect();
+
+        for (i, recipe) in cookbook.recipes.iter().enumerate() {
+            let earlier = &cookbook.recipes[..i];
+            if let Some(first) = earlier.iter().find(|r| *r.name == *recipe.name) {
+                analyses[i]
+                    .problems
+                    .push(Problem::DuplicateRecipe(first.name, recipe.name));
+            }
+        }
+
+        // edges[i] holds each reference in recipe `i` along with the
+        // index of the recipe it refers to
+        let mut edges: Vec<Vec<(StringRef, usize)>> = Vec::new();
+        for (i, recipe) in cookbook.recipes.iter().enumerate() {
+            let mut links = Vec::new();
+            for r in recipe.sub_recipes(state) {
+                match cookbook.recipes.iter().position(|other| *other.name == *r) {
+                    Some(j) => links.push((r, j)),
+                    None => analyses[i].problems.push(Problem::UnknownRecipe(r)),
+                }
+            }
+            edges.push(links);
+        }
+
+        let mut finished = BTreeSet::new();
+        let mut cycles = Vec::new();
+        for i in 0..edges.len() {
+            Analysis::recipe_cycle_search(
+                &edges,
+                &mut vec![i],
+                &mut Vec::new(),
+                &mut finished,
+                &mut cycles,
+            );
+        }
+        for (i, cycle) in cycles.into_iter() {
+            analyses[i].problems.push(Problem::RecipeCycle(cycle));
+        }
+
+        analyses
+    }
+
+    /// This is the same search as `cycle_search`, but over recipes
+    /// instead of join points. Each cycle is paired with the recipe it
+    /// starts from.
+    fn recipe_cycle_search(
+        edges: &[Vec<(StringRef, usize)>],
+        nodes: &mut Vec<usize>,
+        links: &mut Vec<StringRef>,
+        finished: &mut BTreeSet<usize>,
+        cycles: &mut Vec<(usize, Vec<StringRef>)>,
+    ) {
+        let node = nodes[nodes.len() - 1];
+        if finished.contains(&node) {
+            return;
+        }
+        for (r, next) in edges[node].iter() {
+            if let Some(pos) = nodes.iter().position(|n| n == next) {
+                let mut cycle = links[pos..].to_vec();
+                cycle.push(*r);
+                cycles.push((nodes[pos], cycle));
+            } else {
+                nodes.push(*next);
+                links.push(*r);
+                Analysis::recipe_cycle_search(edges, nodes, links, finished, cycles);
+                nodes.pop();
+                links.pop();
+            }
+        }
+        finished.insert(node);
     }
 
     /// Take a `Recipe` and produce an `Analysis` value from it. This
@@ -246,6 +818,7 @@ impl Analysis {
     /// cannot be turned into a `BackwardTree`.
     pub fn from_recipe(state: &State, recipe: &Recipe) -> Self {
         let mut analysis = Analysis {
+            name: recipe.name,
             map: BTreeMap::new(),
             problems: Vec::new(),
         };
@@ -255,19 +828,40 @@ impl Analysis {
             let mut path = Path {
                 actions: Vec::new(),
                 start: rule.input.clone(),
+                end: None,
             };
-            for action in rule.actions.iter() {
+            for (i, action) in rule.actions.iter().enumerate() {
                 match action {
                     Action::Action { step } => path.actions.push(step.clone()),
                     Action::Join { point } => {
+                        path.end = Some(*point);
                         analysis.add(Some(*point), path);
                         path = Path {
                             actions: Vec::new(),
                             start: Input::Join { point: *point },
+                            end: None,
                         };
                     }
-                    Action::Done => {
+                    Action::Done { at } => {
                         analysis.add(None, path);
+                        let rest = &rule.actions[i + 1..];
+                        let dones: Vec<Loc<()>> = rest
+                            .iter()
+                            .filter_map(|a| match a {
+                                Action::Done { at } => Some(*at),
+                                _ => None,
+                            })
+                            .collect();
+                        if !rest.is_empty() {
+                            analysis
+                                .problems
+                                .push(Problem::StepsAfterDone(*at, rest.to_vec()));
+                        }
+                        if !dones.is_empty() {
+                            let mut all = vec![*at];
+                            all.extend(dones);
+                            analysis.problems.push(Problem::MultipleDone(all));
+                        }
                         continue 'outer;
                     }
                 }
@@ -282,15 +876,19 @@ impl Analysis {
         }
 
         if !analysis.map.contains_key(&None) {
-            analysis.problems.push(Problem::NoDone);
-        } else {
-            analysis.find_cycles();
+            analysis.problems.push(Problem::NoDone(recipe.name));
         }
+        analysis.find_cycles();
+        analysis.find_disconnected();
 
         analysis
     }
 
-    fn convert_tree_helper(&mut self, path: Path, vec: &mut Vec<BackwardTree>) -> (usize, usize) {
+    fn convert_tree_helper(
+        &mut self,
+        path: Path,
+        vec: &mut Vec<BackwardTree>,
+    ) -> Result<(usize, usize), Problem> {
         let mut size = 0;
         let mut children = Vec::new();
         let ingredients;
@@ -302,9 +900,16 @@ impl Analysis {
             }
             Input::Join { point } => {
                 ingredients = Vec::new();
-                let paths = self.map.remove(&Some(point.value)).unwrap();
+                // we remove each join point as we go, so a join point
+                // we've already visited looks just like one that
+                // doesn't exist: either way, the analysis should have
+                // caught it already
+                let paths = self
+                    .map
+                    .remove(&Some(point.value))
+                    .ok_or_else(|| Problem::UndefinedJoinPoint(vec![point]))?;
                 for path in paths.into_iter() {
-                    let (ns, nd) = self.convert_tree_helper(path, &mut children);
+                    let (ns, nd) = self.convert_tree_helper(path, &mut children)?;
                     size += ns;
                     max_depth = max(max_depth, nd);
                 }
@@ -318,7 +923,7 @@ impl Analysis {
             size,
             max_depth,
         });
-        (size, max_depth)
+        Ok((size, max_depth))
     }
 
     /// Take an `Analysis` value and convert it into a
@@ -340,9 +945,13 @@ impl Analysis {
             size: 0,
             max_depth: 0,
         };
-        let paths = self.map.remove(&None).unwrap();
+        let paths = self.map.remove(&None).ok_or(Problems {
+            problems: vec![Problem::NoDone(self.name)],
+        })?;
         for path in paths.into_iter() {
-            let (ns, nd) = self.convert_tree_helper(path, &mut b.paths);
+            let (ns, nd) = self
+                .convert_tree_helper(path, &mut b.paths)
+                .map_err(|p| Problems { problems: vec![p] })?;
             b.size += ns;
             b.max_depth = max(b.max_depth, nd);
         }
@@ -350,6 +959,113 @@ impl Analysis {
     }
 }
 
+impl BackwardTree {
+    /// Replace every `@name` ingredient in this tree with the tree for
+    /// that recipe, taken from `trees`, so that the whole thing can be
+    /// drawn as a single recipe. References to recipes that aren't in
+    /// `trees` (and references used as seasonings) are left alone, to
+    /// be rendered as links to a separate recipe.
+    pub fn inline_sub_recipes(
+        &self,
+        state: &State,
+        trees: &BTreeMap<string_interner::DefaultSymbol, BackwardTree>,
+    ) -> BackwardTree {
+        let mut stack = Vec::new();
+        let paths: Vec<BackwardTree> = self
+            .paths
+            .iter()
+            .flat_map(|path| path.inline_helper(state, trees, &mut stack))
+            .collect();
+        BackwardTree {
+            size: paths.iter().map(|p| p.size).sum(),
+            max_depth: paths.iter().map(|p| p.max_depth).max().unwrap_or(0),
+            actions: Vec::new(),
+            ingredients: Vec::new(),
+            paths,
+        }
+    }
+
+    /// Inline the sub-recipes of a non-root node. This returns a list
+    /// of nodes because a node whose only input was a sub-recipe and
+    /// which has no actions of its own just gets replaced by the
+    /// paths of that sub-recipe.
+    fn inline_helper(
+        &self,
+        state: &State,
+        trees: &BTreeMap<string_interner::DefaultSymbol, BackwardTree>,
+        stack: &mut Vec<string_interner::DefaultSymbol>,
+    ) -> Vec<BackwardTree> {
+        let mut paths: Vec<BackwardTree> = self
+            .paths
+            .iter()
+            .flat_map(|path| path.inline_helper(state, trees, stack))
+            .collect();
+        let mut ingredients = Vec::new();
+        let mut inlined = Vec::new();
+        for i in self.ingredients.iter() {
+            let ingredient = &state[*i];
+            let name = ingredient.stuff.value;
+            // the stack check is only to avoid looping forever: a
+            // cookbook with a cycle in it won't pass analysis anyway
+            match trees.get(&name) {
+                Some(tree) if ingredient.sub_recipe && !stack.contains(&name) => {
+                    stack.push(name);
+                    inlined.extend(
+                        tree.paths
+                            .iter()
+                            .flat_map(|path| path.inline_helper(state, trees, stack)),
+                    );
+                    stack.pop();
+                }
+                _ => ingredients.push(*i),
+            }
+        }
+
+        if inlined.is_empty() {
+            // nothing to do here, so we don't need to restructure
+            // anything
+            let size = if paths.is_empty() {
+                ingredients.len()
+            } else {
+                paths.iter().map(|p| p.size).sum()
+            };
+            let max_depth =
+                self.actions.len() + paths.iter().map(|p| p.max_depth).max().unwrap_or(0);
+            return vec![BackwardTree {
+                actions: self.actions.clone(),
+                paths,
+                ingredients,
+                size,
+                max_depth,
+            }];
+        }
+
+        // only leaves can have ingredients, so any plain ingredients
+        // alongside a sub-recipe get a leaf of their own
+        if !ingredients.is_empty() {
+            paths.push(BackwardTree {
+                actions: Vec::new(),
+                paths: Vec::new(),
+                size: ingredients.len(),
+                max_depth: 0,
+                ingredients,
+            });
+        }
+        paths.extend(inlined);
+
+        if self.actions.is_empty() {
+            return paths;
+        }
+        vec![BackwardTree {
+            actions: self.actions.clone(),
+            size: paths.iter().map(|p| p.size).sum(),
+            max_depth: self.actions.len() + paths.iter().map(|p| p.max_depth).max().unwrap_or(0),
+            ingredients: Vec::new(),
+            paths,
+        }]
+    }
+}
+
 impl ToPrintable for BackwardTree {}
 
 impl<'a> fmt::Debug for Printable<'a, BackwardTree> {
@@ -376,7 +1092,7 @@ impl<'a> fmt::Debug for Printable<'a, Path> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut str = f.debug_list();
         str.entry(&self.from_val(&self.value.start));
-        str.entries(&self.from_seq(&self.value.actions));
+        str.entries(self.from_seq(&self.value.actions));
         str.finish()
     }
 }
@@ -0,0 +1,198 @@
+//! The `diagnostics` module renders errors alongside the lines of
+//! source they refer to, with the relevant parts underlined.
+
+use crate::errors::Position;
+
+/// A region of the source that a `Diagnostic` wants to point at. A
+/// primary label marks the thing that's actually wrong (underlined
+/// with `^`), while secondary labels add context (underlined with
+/// `-`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub l: usize,
+    pub r: usize,
+    pub message: String,
+    pub primary: bool,
+}
+
+impl Label {
+    pub fn primary(l: usize, r: usize, message: impl Into<String>) -> Label {
+        Label {
+            l,
+            r,
+            message: message.into(),
+            primary: true,
+        }
+    }
+
+    pub fn secondary(l: usize, r: usize, message: impl Into<String>) -> Label {
+        Label {
+            l,
+            r,
+            message: message.into(),
+            primary: false,
+        }
+    }
+}
+
+/// Whether a `Diagnostic` stops us from doing anything with the
+/// recipe, or is just something the author should know about
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// An error message along with the labelled source regions that
+/// explain it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+}
+
+/// The part of a single label that falls on a single line
+struct Segment<'a> {
+    line: usize,
+    start: usize,
+    width: usize,
+    primary: bool,
+    message: Option<&'a str>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            labels: Vec::new(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::new(message)
+        }
+    }
+
+    pub fn with_label(mut self, label: Label) -> Diagnostic {
+        self.labels.push(label);
+        self
+    }
+
+    /// Render the diagnostic, e.g.
+    ///
+    /// ```text
+    /// error: these steps never reach a join point or `<>`
+    ///  --> line 2, column 27
+    ///   |
+    /// 2 |   eggs -> bake -> $foo -> enter the void;
+    ///   |                   ---- starting from this join point
+    ///   |                           ^^^^^^^^^^^^^^ these steps don't lead anywhere
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let lines: Vec<&str> = source.lines().collect();
+        let mut segments = Vec::new();
+        for label in self.labels.iter() {
+            segments.extend(Diagnostic::segments(source, &lines, label));
+        }
+        segments.sort_by_key(|s| (s.line, s.start));
+
+        let level = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let mut buf = format!("{}: {}\n", level, self.message);
+        let last_line = segments.iter().map(|s| s.line).max().unwrap_or(1);
+        let gutter = " ".repeat(last_line.to_string().len());
+
+        // point at the first primary label, or failing that, at
+        // anything at all
+        let anchor = self
+            .labels
+            .iter()
+            .find(|l| l.primary)
+            .or_else(|| self.labels.first());
+        if let Some(anchor) = anchor {
+            let Position { line, column } = Position::from_offset(source, anchor.l);
+            buf.push_str(&format!("{}--> line {}, column {}\n", gutter, line, column));
+        }
+        if segments.is_empty() {
+            return buf;
+        }
+
+        buf.push_str(&format!("{} |\n", gutter));
+        let mut prev_line: Option<usize> = None;
+        for seg in segments.iter() {
+            if prev_line != Some(seg.line) {
+                if let Some(prev) = prev_line {
+                    if seg.line > prev + 1 {
+                        buf.push_str("...\n");
+                    }
+                }
+                buf.push_str(&format!(
+                    "{:>width$} | {}\n",
+                    seg.line,
+                    lines.get(seg.line - 1).unwrap_or(&""),
+                    width = gutter.len()
+                ));
+                prev_line = Some(seg.line);
+            }
+            let marker = if seg.primary { "^" } else { "-" };
+            let mut underline = format!(
+                "{} | {}{}",
+                gutter,
+                " ".repeat(seg.start - 1),
+                marker.repeat(seg.width)
+            );
+            if let Some(msg) = seg.message.filter(|m| !m.is_empty()) {
+                underline.push(' ');
+                underline.push_str(msg);
+            }
+            buf.push_str(underline.trim_end());
+            buf.push('\n');
+        }
+        buf
+    }
+
+    /// Split a label into the per-line pieces we need to underline,
+    /// attaching the message to the last one
+    fn segments<'a>(source: &str, lines: &[&str], label: &'a Label) -> Vec<Segment<'a>> {
+        let start = Position::from_offset(source, label.l);
+        let end = Position::from_offset(source, label.r);
+        let mut segments = Vec::new();
+        for line in start.line..=end.line {
+            let text = lines.get(line - 1).unwrap_or(&"");
+            // continuation lines are underlined starting after their
+            // indentation
+            let from = if line == start.line {
+                start.column
+            } else {
+                text.chars().take_while(|c| c.is_whitespace()).count() + 1
+            };
+            let to = if line == end.line {
+                end.column
+            } else {
+                text.chars().count() + 1
+            };
+            if to <= from && line != start.line {
+                continue;
+            }
+            segments.push(Segment {
+                line,
+                start: from,
+                // always underline at least one character, so
+                // zero-width labels still have something to point at
+                width: to.saturating_sub(from).max(1),
+                primary: label.primary,
+                message: None,
+            });
+        }
+        if let Some(last) = segments.last_mut() {
+            last.message = Some(&label.message);
+        }
+        segments
+    }
+}
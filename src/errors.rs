@@ -0,0 +1,210 @@
+//! The `errors` module contains the error types produced when we
+//! can't make sense of an Apicius source file.
+
+use lalrpop_util::lexer::Token;
+
+use crate::diagnostics::{Diagnostic, Label};
+use thiserror::Error;
+
+/// A human-readable position in a source file. Both fields are
+/// 1-indexed, and `column` counts characters rather than bytes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    /// Find the line and column of a byte offset into `source`
+    pub fn from_offset(source: &str, offset: usize) -> Position {
+        let offset = offset.min(source.len());
+        let before = &source[..offset];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Position {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+/// The region of the source an error refers to, as both byte offsets
+/// (`l` and `r`, like a `Loc`) and line/column positions
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub l: usize,
+    pub r: usize,
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(source: &str, l: usize, r: usize) -> Span {
+        Span {
+            l,
+            r,
+            start: Position::from_offset(source, l),
+            end: Position::from_offset(source, r),
+        }
+    }
+}
+
+/// The specific thing that went wrong while parsing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// We found a token, but not one that can appear here
+    UnexpectedToken {
+        token: String,
+        expected: Vec<String>,
+    },
+    /// The file ended in the middle of a recipe
+    UnexpectedEof { expected: Vec<String> },
+    /// The lexer couldn't turn the input into any token at all
+    InvalidToken,
+    /// A `"` with no matching `"` later on the same line
+    UnterminatedQuote,
+    /// We finished parsing but there was still more input
+    ExtraToken { token: String },
+}
+
+/// A parse failure, including where in the source it happened
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("parse error at line {}, column {}: {}", .span.start.line, .span.start.column, .kind.message())]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+}
+
+impl ParseErrorKind {
+    /// A one-line description of the error without any location
+    /// information
+    pub fn message(&self) -> String {
+        match self {
+            ParseErrorKind::UnexpectedToken { token, expected } => {
+                format!("unexpected `{}`{}", token, describe_expected(expected))
+            }
+            ParseErrorKind::UnexpectedEof { expected } => {
+                format!("unexpected end of input{}", describe_expected(expected))
+            }
+            ParseErrorKind::InvalidToken => "unrecognized character".to_string(),
+            ParseErrorKind::UnterminatedQuote => "quoted text is never closed".to_string(),
+            ParseErrorKind::ExtraToken { token } => {
+                format!("unexpected `{}` after the end of the recipe", token)
+            }
+        }
+    }
+}
+
+fn describe_expected(expected: &[String]) -> String {
+    match expected {
+        [] => String::new(),
+        [only] => format!("; expected {}", only),
+        [init @ .., last] => format!("; expected {} or {}", init.join(", "), last),
+    }
+}
+
+/// LALRPOP reports expected terminals using their names in the
+/// grammar, so turn those into something a recipe author would
+/// recognize
+fn describe_terminal(terminal: &str) -> String {
+    match terminal {
+        // quoted text is just another way of writing text, so we
+        // don't need to mention it separately
+        "TEXT" | "QUOTED" => "text".to_string(),
+        "JOIN_POINT" => "a join point".to_string(),
+        _ => format!("`{}`", terminal.trim_matches('"')),
+    }
+}
+
+type RawParseError<'input> = lalrpop_util::ParseError<usize, Token<'input>, &'static str>;
+
+impl ParseError {
+    /// Convert LALRPOP's error into a `ParseError`, using the source
+    /// text to compute line and column information
+    pub fn from_lalrpop(source: &str, err: RawParseError) -> ParseError {
+        let describe_all = |expected: Vec<String>| -> Vec<String> {
+            let mut described: Vec<String> = Vec::new();
+            for e in expected.iter().map(|e| describe_terminal(e)) {
+                if !described.contains(&e) {
+                    described.push(e);
+                }
+            }
+            described
+        };
+        let (kind, l, r) = match err {
+            lalrpop_util::ParseError::UnrecognizedToken {
+                token: (l, token, r),
+                expected,
+            } => (
+                ParseErrorKind::UnexpectedToken {
+                    token: token.1.trim().to_string(),
+                    expected: describe_all(expected),
+                },
+                l,
+                r,
+            ),
+            lalrpop_util::ParseError::UnrecognizedEOF { location, expected } => (
+                ParseErrorKind::UnexpectedEof {
+                    expected: describe_all(expected),
+                },
+                location,
+                location,
+            ),
+            // quoted text can't span lines, so the only way the lexer
+            // fails on a quote is if it doesn't end on the same line
+            lalrpop_util::ParseError::InvalidToken { location }
+                if source[location..].starts_with('"') =>
+            {
+                let width = source[location..]
+                    .find('\n')
+                    .unwrap_or(source.len() - location);
+                (
+                    ParseErrorKind::UnterminatedQuote,
+                    location,
+                    location + width,
+                )
+            }
+            lalrpop_util::ParseError::InvalidToken { location } => {
+                let width = source[location..].chars().next().map_or(0, char::len_utf8);
+                (ParseErrorKind::InvalidToken, location, location + width)
+            }
+            lalrpop_util::ParseError::ExtraToken {
+                token: (l, token, r),
+            } => (
+                ParseErrorKind::ExtraToken {
+                    token: token.1.trim().to_string(),
+                },
+                l,
+                r,
+            ),
+            // our grammar doesn't produce any user errors
+            lalrpop_util::ParseError::User { error } => (
+                ParseErrorKind::UnexpectedToken {
+                    token: error.to_string(),
+                    expected: Vec::new(),
+                },
+                0,
+                0,
+            ),
+        };
+        ParseError {
+            kind,
+            span: Span::new(source, l, r),
+        }
+    }
+
+    /// Render the error along with the line of source it happened
+    /// on, with the offending region underlined, e.g.
+    ///
+    /// ```text
+    /// error: unexpected `->`; expected text
+    ///  --> line 2, column 11
+    ///   |
+    /// 2 |   eggs -> -> <>;
+    ///   |           ^^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        Diagnostic::new(self.kind.message())
+            .with_label(Label::primary(self.span.l, self.span.r, ""))
+            .render(source)
+    }
+}
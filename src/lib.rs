@@ -2,8 +2,16 @@
 extern crate lalrpop_util;
 
 pub mod checks;
+pub mod diagnostics;
+pub mod errors;
+pub mod quantity;
 pub mod render;
+pub mod scale;
+pub mod schedule;
+pub mod shopping;
+pub mod timing;
 pub mod types;
+pub mod units;
 
 #[cfg(test)]
 pub mod test {
@@ -11,3 +19,34 @@ pub mod test {
 }
 
 lalrpop_mod!(#[allow(clippy::all)] pub grammar);
+
+// building the lexer means compiling some fairly large Unicode-aware
+// regexes, so we only want to do that once rather than on every parse
+fn recipe_parser() -> &'static grammar::RecipeParser {
+    static PARSER: std::sync::OnceLock<grammar::RecipeParser> = std::sync::OnceLock::new();
+    PARSER.get_or_init(grammar::RecipeParser::new)
+}
+
+fn cookbook_parser() -> &'static grammar::CookbookParser {
+    static PARSER: std::sync::OnceLock<grammar::CookbookParser> = std::sync::OnceLock::new();
+    PARSER.get_or_init(grammar::CookbookParser::new)
+}
+
+/// Parse a single recipe from Apicius source, adding its data to the
+/// given `State`
+pub fn parse(state: &mut types::State, source: &str) -> Result<types::Recipe, errors::ParseError> {
+    recipe_parser()
+        .parse(state, source)
+        .map_err(|err| errors::ParseError::from_lalrpop(source, err))
+}
+
+/// Parse every recipe in a file of Apicius source, adding their data
+/// to the given `State`
+pub fn parse_cookbook(
+    state: &mut types::State,
+    source: &str,
+) -> Result<types::Cookbook, errors::ParseError> {
+    cookbook_parser()
+        .parse(state, source)
+        .map_err(|err| errors::ParseError::from_lalrpop(source, err))
+}
@@ -0,0 +1,546 @@
+//! The `quantity` module turns the free-form text of an ingredient's
+//! amount (the `1 1/2 cups` in `[1 1/2 cups] flour`) into something
+//! we can do arithmetic on.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::units::System;
+
+/// An exact fraction, always kept in lowest terms with a positive
+/// denominator. Arithmetic is done with 128-bit intermediates, and a
+/// result too big to fit back in 64 bits is rounded to the nearest
+/// millionth (or the nearest integer, for enormous numbers) rather
+/// than overflowing: that's still far more precise than any recipe
+/// needs. The `checked_*` methods say when that would happen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Rational {
+    num: i64,
+    den: i64,
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl Rational {
+    /// Create a new fraction, normalizing it. This will panic if
+    /// `den` is zero.
+    pub fn new(num: i64, den: i64) -> Rational {
+        assert!(den != 0, "Rational with a zero denominator");
+        Rational::wide(num as i128, den as i128)
+            .unwrap_or_else(|| Rational::approximate(num as i128, den as i128))
+    }
+
+    /// Normalize a fraction computed with wider intermediates, or
+    /// `None` if it still doesn't fit once it's in lowest terms
+    fn wide(num: i128, den: i128) -> Option<Rational> {
+        let g = gcd(num, den).max(1);
+        let sign = if den < 0 { -1 } else { 1 };
+        Some(Rational {
+            num: i64::try_from(sign * num / g).ok()?,
+            den: i64::try_from(sign * den / g).ok()?,
+        })
+    }
+
+    /// The closest fraction to `num / den` that fits, for when the
+    /// exact one doesn't
+    fn approximate(num: i128, den: i128) -> Rational {
+        let value = num as f64 / den as f64;
+        let den = if value.abs() < (i64::MAX / 1_000_000) as f64 {
+            1_000_000
+        } else {
+            1
+        };
+        // `as` saturates at the ends of the range
+        Rational::new((value * den as f64).round() as i64, den)
+    }
+
+    pub fn checked_add(self, other: Rational) -> Option<Rational> {
+        Rational::wide(
+            self.num as i128 * other.den as i128 + other.num as i128 * self.den as i128,
+            self.den as i128 * other.den as i128,
+        )
+    }
+
+    pub fn checked_mul(self, other: Rational) -> Option<Rational> {
+        Rational::wide(
+            self.num as i128 * other.num as i128,
+            self.den as i128 * other.den as i128,
+        )
+    }
+
+    /// Divide, or `None` if the result doesn't fit or `other` is zero
+    pub fn checked_div(self, other: Rational) -> Option<Rational> {
+        if other.num == 0 {
+            return None;
+        }
+        Rational::wide(
+            self.num as i128 * other.den as i128,
+            self.den as i128 * other.num as i128,
+        )
+    }
+
+    pub fn from_integer(n: i64) -> Rational {
+        Rational { num: n, den: 1 }
+    }
+
+    pub fn numerator(&self) -> i64 {
+        self.num
+    }
+
+    pub fn denominator(&self) -> i64 {
+        self.den
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.den == 1
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    /// Format this number the way a recipe would write it: as a
+    /// mixed number like `1 1/2` when it's a fraction a cook would
+    /// measure out, and as a decimal like `0.2` otherwise
+    pub fn pretty(&self) -> String {
+        if self.den == 1 {
+            return self.num.to_string();
+        }
+        if [2, 3, 4, 6, 8].contains(&self.den) {
+            let whole = self.num / self.den;
+            let rem = (self.num % self.den).abs();
+            return if whole == 0 {
+                format!("{}/{}", self.num, self.den)
+            } else {
+                format!("{} {}/{}", whole, rem, self.den)
+            };
+        }
+        self.decimal()
+    }
+
+    /// Format this number as a decimal with at most two places
+    pub fn decimal(&self) -> String {
+        if self.den == 1 {
+            return self.num.to_string();
+        }
+        let decimal = format!("{:.2}", self.to_f64());
+        decimal
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    }
+
+    /// Round to the nearest multiple of `step`
+    pub fn round_to(&self, step: Rational) -> Rational {
+        let steps = *self / step;
+        let nearest = (2 * steps.num as i128 + steps.den as i128).div_euclid(2 * steps.den as i128);
+        Rational::from_integer(nearest as i64) * step
+    }
+
+    /// Parse a single number written as an integer (`2`), a decimal
+    /// (`1.5`), a fraction (`1/2`), a Unicode fraction (`½`) or an
+    /// integer followed by a Unicode fraction (`1½`)
+    pub fn parse(s: &str) -> Option<Rational> {
+        if s.is_empty() {
+            return None;
+        }
+        if let Some((n, d)) = s.split_once('/') {
+            let d = parse_digits(d)?;
+            if d == 0 {
+                return None;
+            }
+            return Some(Rational::new(parse_digits(n)?, d));
+        }
+        if let Some((whole, frac)) = s.split_once('.') {
+            if frac.is_empty() || frac.len() > 9 {
+                return None;
+            }
+            let whole = if whole.is_empty() {
+                0
+            } else {
+                parse_digits(whole)?
+            };
+            let den = 10i64.pow(frac.len() as u32);
+            let num = whole.checked_mul(den)?.checked_add(parse_digits(frac)?)?;
+            return Some(Rational::new(num, den));
+        }
+        let last = s.chars().last()?;
+        if let Some(frac) = vulgar_fraction(last) {
+            let whole = &s[..s.len() - last.len_utf8()];
+            if whole.is_empty() {
+                return Some(frac);
+            }
+            return Rational::from_integer(parse_digits(whole)?).checked_add(frac);
+        }
+        parse_digits(s).map(Rational::from_integer)
+    }
+}
+
+fn parse_digits(s: &str) -> Option<i64> {
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+fn vulgar_fraction(c: char) -> Option<Rational> {
+    let (n, d) = match c {
+        '½' => (1, 2),
+        '⅓' => (1, 3),
+        '⅔' => (2, 3),
+        '¼' => (1, 4),
+        '¾' => (3, 4),
+        '⅕' => (1, 5),
+        '⅖' => (2, 5),
+        '⅗' => (3, 5),
+        '⅘' => (4, 5),
+        '⅙' => (1, 6),
+        '⅚' => (5, 6),
+        '⅛' => (1, 8),
+        '⅜' => (3, 8),
+        '⅝' => (5, 8),
+        '⅞' => (7, 8),
+        _ => return None,
+    };
+    Some(Rational::new(n, d))
+}
+
+impl std::ops::Add for Rational {
+    type Output = Rational;
+
+    fn add(self, other: Rational) -> Rational {
+        self.checked_add(other).unwrap_or_else(|| {
+            Rational::approximate(
+                self.num as i128 * other.den as i128 + other.num as i128 * self.den as i128,
+                self.den as i128 * other.den as i128,
+            )
+        })
+    }
+}
+
+impl std::ops::Mul for Rational {
+    type Output = Rational;
+
+    fn mul(self, other: Rational) -> Rational {
+        self.checked_mul(other).unwrap_or_else(|| {
+            Rational::approximate(
+                self.num as i128 * other.num as i128,
+                self.den as i128 * other.den as i128,
+            )
+        })
+    }
+}
+
+impl std::ops::Div for Rational {
+    type Output = Rational;
+
+    /// This will panic if `other` is zero, just like integer division
+    fn div(self, other: Rational) -> Rational {
+        assert!(other.num != 0, "Rational division by zero");
+        self.checked_div(other).unwrap_or_else(|| {
+            Rational::approximate(
+                self.num as i128 * other.den as i128,
+                self.den as i128 * other.num as i128,
+            )
+        })
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Rational) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Rational) -> std::cmp::Ordering {
+        (self.num as i128 * other.den as i128).cmp(&(other.num as i128 * self.den as i128))
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+/// How much of something there is: either an exact amount or a range
+/// like `2-3`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Value {
+    Exact(Rational),
+    Range(Rational, Rational),
+}
+
+impl Value {
+    /// Multiply this value (or both ends of the range) by a factor
+    pub fn scale(&self, factor: Rational) -> Value {
+        match self {
+            Value::Exact(n) => Value::Exact(*n * factor),
+            Value::Range(lo, hi) => Value::Range(*lo * factor, *hi * factor),
+        }
+    }
+
+    /// The largest amount this value could be
+    pub fn max(&self) -> Rational {
+        match self {
+            Value::Exact(n) => *n,
+            Value::Range(_, hi) => *hi,
+        }
+    }
+}
+
+impl std::ops::Add for Value {
+    type Output = Value;
+
+    fn add(self, other: Value) -> Value {
+        match (self, other) {
+            (Value::Exact(a), Value::Exact(b)) => Value::Exact(a + b),
+            (Value::Exact(a), Value::Range(lo, hi)) | (Value::Range(lo, hi), Value::Exact(a)) => {
+                Value::Range(lo + a, hi + a)
+            }
+            (Value::Range(lo1, hi1), Value::Range(lo2, hi2)) => Value::Range(lo1 + lo2, hi1 + hi2),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Exact(n) => write!(f, "{}", n.pretty()),
+            Value::Range(lo, hi) => write!(f, "{}-{}", lo.pretty(), hi.pretty()),
+        }
+    }
+}
+
+/// The unit an amount is measured in. Units we know about get their
+/// own variant, and anything else (`clove`, `can`, `bunch`) is kept
+/// as written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unit {
+    Teaspoon,
+    Tablespoon,
+    Cup,
+    FluidOunce,
+    Pint,
+    Quart,
+    Gallon,
+    Millilitre,
+    Litre,
+    Milligram,
+    Gram,
+    Kilogram,
+    Ounce,
+    Pound,
+    Pinch,
+    Dash,
+    Other(String),
+}
+
+/// Every unit we know about, along with all the ways we accept it
+/// being written. The first spelling is the one we use for display.
+const UNITS: &[(Unit, &[&str])] = &[
+    (
+        Unit::Teaspoon,
+        &["tsp", "tsps", "teaspoon", "teaspoons", "t"],
+    ),
+    (
+        Unit::Tablespoon,
+        &["tbsp", "tbsps", "tablespoon", "tablespoons", "tbs", "tbl"],
+    ),
+    (Unit::Cup, &["cup", "cups", "c"]),
+    (
+        Unit::FluidOunce,
+        &["fl oz", "fl. oz.", "fluid ounce", "fluid ounces", "floz"],
+    ),
+    (Unit::Pint, &["pint", "pints", "pt"]),
+    (Unit::Quart, &["quart", "quarts", "qt"]),
+    (Unit::Gallon, &["gallon", "gallons", "gal"]),
+    (
+        Unit::Millilitre,
+        &[
+            "ml",
+            "millilitre",
+            "millilitres",
+            "milliliter",
+            "milliliters",
+        ],
+    ),
+    (Unit::Litre, &["l", "litre", "litres", "liter", "liters"]),
+    (
+        Unit::Milligram,
+        &[
+            "mg",
+            "milligram",
+            "milligrams",
+            "milligramme",
+            "milligrammes",
+        ],
+    ),
+    (Unit::Gram, &["g", "gram", "grams", "gramme", "grammes"]),
+    (
+        Unit::Kilogram,
+        &["kg", "kilogram", "kilograms", "kilo", "kilos"],
+    ),
+    (Unit::Ounce, &["oz", "ounce", "ounces"]),
+    (Unit::Pound, &["lb", "lbs", "pound", "pounds"]),
+    (Unit::Pinch, &["pinch", "pinches"]),
+    (Unit::Dash, &["dash", "dashes"]),
+];
+
+impl Unit {
+    /// Find the unit a piece of text refers to, falling back to
+    /// `Unit::Other` if it's not one we know
+    pub fn parse(s: &str) -> Unit {
+        // the one place where case matters: `T` is a tablespoon
+        // while `t` is a teaspoon
+        if s == "T" {
+            return Unit::Tablespoon;
+        }
+        let lower = s.to_lowercase();
+        let lower = lower.trim_end_matches('.');
+        UNITS
+            .iter()
+            .find(|(_, names)| names.iter().any(|n| n.trim_end_matches('.') == lower))
+            .map(|(unit, _)| unit.clone())
+            .unwrap_or_else(|| Unit::Other(s.to_string()))
+    }
+
+    /// The usual short name for this unit
+    pub fn name(&self) -> &str {
+        match self {
+            Unit::Other(name) => name,
+            known => UNITS.iter().find(|(u, _)| u == known).unwrap().1[0],
+        }
+    }
+
+    /// The name to use for more than one of this unit. Abbreviations
+    /// like `tbsp` don't change, and units we don't know about are
+    /// left however they were written.
+    pub fn plural_name(&self) -> &str {
+        match self {
+            Unit::Cup => "cups",
+            Unit::Pint => "pints",
+            Unit::Quart => "quarts",
+            Unit::Gallon => "gallons",
+            Unit::Pinch => "pinches",
+            Unit::Dash => "dashes",
+            other => other.name(),
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A structured amount, e.g. `1 1/2 cups` or `2-3 cloves`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quantity {
+    pub value: Value,
+    pub unit: Option<Unit>,
+}
+
+impl Quantity {
+    /// Try to understand the text of an amount. This returns `None`
+    /// for amounts that don't start with a number, like `to taste`.
+    pub fn parse(s: &str) -> Option<Quantity> {
+        let s = s.trim();
+        let (lo, rest) = parse_number(s)?;
+        let rest = rest.trim_start();
+
+        // a range can be written as `2-3`, `2–3` or `2 to 3`
+        for sep in ["-", "–", "to "] {
+            if let Some(after) = rest.strip_prefix(sep) {
+                if let Some((hi, after)) = parse_number(after.trim_start()) {
+                    return Some(Quantity::with_unit(Value::Range(lo, hi), after));
+                }
+            }
+        }
+        Some(Quantity::with_unit(Value::Exact(lo), rest))
+    }
+
+    /// Multiply this quantity by a factor, keeping the same unit
+    pub fn scale(&self, factor: Rational) -> Quantity {
+        Quantity {
+            value: self.value.scale(factor),
+            unit: self.unit.clone(),
+        }
+    }
+
+    fn with_unit(value: Value, unit: &str) -> Quantity {
+        let unit = unit.trim();
+        Quantity {
+            value,
+            unit: if unit.is_empty() {
+                None
+            } else {
+                Some(Unit::parse(unit))
+            },
+        }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // nobody measures out 3/4 of a litre, so metric amounts are
+        // written as decimals
+        let metric = self.unit.as_ref().map(Unit::system) == Some(System::Metric);
+        match self.value {
+            Value::Exact(n) if metric => write!(f, "{}", n.decimal())?,
+            Value::Range(lo, hi) if metric => write!(f, "{}-{}", lo.decimal(), hi.decimal())?,
+            value => write!(f, "{}", value)?,
+        }
+        if let Some(unit) = &self.unit {
+            if self.value.max() > Rational::from_integer(1) {
+                write!(f, " {}", unit.plural_name())?;
+            } else {
+                write!(f, " {}", unit.name())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pull a number off the front of a string, returning the rest. This
+/// handles `a`/`an` as one, and mixed numbers like `1 1/2`.
+fn parse_number(s: &str) -> Option<(Rational, &str)> {
+    for article in ["a ", "an "] {
+        if let Some(rest) = s.strip_prefix(article) {
+            return Some((Rational::from_integer(1), rest));
+        }
+    }
+
+    let is_number_char =
+        |c: char| c.is_ascii_digit() || c == '.' || c == '/' || vulgar_fraction(c).is_some();
+    let end = s.find(|c| !is_number_char(c)).unwrap_or(s.len());
+    let n = Rational::parse(&s[..end])?;
+    let rest = &s[end..];
+
+    // a whole number followed by a fraction is a mixed number
+    if n.is_integer() {
+        let trimmed = rest.trim_start();
+        let frac_end = trimmed
+            .find(|c| !is_number_char(c))
+            .unwrap_or(trimmed.len());
+        let frac = &trimmed[..frac_end];
+        if trimmed.len() < rest.len() && frac.contains('/') {
+            if let Some(frac) = Rational::parse(frac) {
+                return Some((n + frac, &trimmed[frac_end..]));
+            }
+        }
+    }
+    Some((n, rest))
+}
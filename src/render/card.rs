@@ -0,0 +1,274 @@
+use crate::checks::BackwardTree;
+use crate::render::drawing::{self, Drawing, Mark, Measure, RenderConfig, Style};
+use crate::render::{graph, table};
+use crate::types::{Recipe, State};
+
+use std::io;
+use std::rc::Rc;
+
+/// The sizes of paper we know how to print on
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PageSize {
+    A4,
+    Letter,
+    /// a 6x4 inch index card, held sideways
+    IndexCard,
+}
+
+impl PageSize {
+    /// The width and height of the page, in points
+    pub fn dimensions(self) -> (f64, f64) {
+        match self {
+            PageSize::A4 => (595.28, 841.89),
+            PageSize::Letter => (612.0, 792.0),
+            PageSize::IndexCard => (432.0, 288.0),
+        }
+    }
+
+    fn margin(self) -> f64 {
+        match self {
+            PageSize::A4 | PageSize::Letter => 48.0,
+            PageSize::IndexCard => 18.0,
+        }
+    }
+}
+
+impl std::str::FromStr for PageSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PageSize, String> {
+        match s {
+            "a4" => Ok(PageSize::A4),
+            "letter" => Ok(PageSize::Letter),
+            "index-card" => Ok(PageSize::IndexCard),
+            _ => Err(format!("unknown page size '{}'", s)),
+        }
+    }
+}
+
+/// Which picture of the recipe goes on the card
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Diagram {
+    Table,
+    Graph,
+}
+
+#[derive(Debug, Clone)]
+pub struct CardOptions {
+    pub page_size: PageSize,
+    pub diagram: Diagram,
+    pub config: RenderConfig,
+}
+
+impl std::default::Default for CardOptions {
+    fn default() -> CardOptions {
+        CardOptions {
+            page_size: PageSize::A4,
+            diagram: Diagram::Table,
+            config: RenderConfig {
+                font_size: 11.0,
+                // the page has its own margins, so the diagram
+                // doesn't need any
+                margin: 0.0,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Something on a page: either a mark in page coordinates, or a
+/// slice of a diagram shrunk down by `scale`. A diagram too tall for
+/// one page is cut into slices `height` high, where `top` is how far
+/// down the scaled diagram the slice starts.
+#[derive(Debug)]
+enum Item {
+    Mark(Mark),
+    Diagram {
+        drawing: Rc<Drawing>,
+        x: f64,
+        y: f64,
+        scale: f64,
+        top: f64,
+        height: f64,
+    },
+}
+
+/// Printable recipe cards, one or more pages per recipe. Each card
+/// has the name of the recipe, what it makes, a list of ingredients
+/// and a diagram of the steps.
+#[derive(Debug)]
+pub struct Cards {
+    options: CardOptions,
+    pages: Vec<Vec<Item>>,
+}
+
+/// Lays things out down the page, starting a new page whenever we run
+/// out of room
+struct Pager<'a> {
+    measure: Measure<'a>,
+    size: (f64, f64),
+    margin: f64,
+    y: f64,
+    pages: Vec<Vec<Item>>,
+}
+
+impl<'a> Pager<'a> {
+    fn new_page(&mut self) {
+        self.pages.push(Vec::new());
+        self.y = self.margin;
+    }
+
+    fn bottom(&self) -> f64 {
+        self.size.1 - self.margin
+    }
+
+    fn width(&self) -> f64 {
+        self.size.0 - 2.0 * self.margin
+    }
+
+    fn paragraph(&mut self, text: &str, style: Style, indent: f64) -> Result<(), cairo::Error> {
+        let width = self.width() - indent;
+        let (_, h) = self.measure.paragraph(text, style, width)?;
+        if self.y + h > self.bottom() && self.y > self.margin {
+            self.new_page();
+        }
+        let item = Item::Mark(Mark::Paragraph {
+            x: self.margin + indent,
+            y: self.y,
+            width,
+            text: text.to_string(),
+            style,
+        });
+        self.pages.last_mut().unwrap().push(item);
+        self.y += h;
+        Ok(())
+    }
+
+    fn gap(&mut self, height: f64) {
+        self.y += height;
+    }
+
+    /// Put a diagram on the page, scaled down so that it fits across
+    /// the page. If it doesn't fit in what's left of the page, it
+    /// moves on to the next one, and if it won't fit on a page at all
+    /// it carries on over as many pages as it takes.
+    fn diagram(&mut self, drawing: Drawing) {
+        let scale = f64::min(1.0, self.width() / drawing.width());
+        let height = drawing.height() * scale;
+        if self.y + height > self.bottom() && self.y > self.margin {
+            self.new_page();
+        }
+        let drawing = Rc::new(drawing);
+        let mut top = 0.0;
+        loop {
+            let slice = f64::min(self.bottom() - self.y, height - top);
+            let item = Item::Diagram {
+                drawing: drawing.clone(),
+                x: self.margin,
+                y: self.y,
+                scale,
+                top,
+                height: slice,
+            };
+            self.pages.last_mut().unwrap().push(item);
+            self.y += slice;
+            top += slice;
+            // anything less than a point left over isn't worth a page
+            if height - top < 1.0 {
+                break;
+            }
+            self.new_page();
+        }
+    }
+}
+
+impl Cards {
+    /// Lay out a card for each recipe, using its tree for the diagram
+    pub fn new(
+        state: &State,
+        recipes: &[(&Recipe, &BackwardTree)],
+        options: &CardOptions,
+    ) -> Result<Cards, cairo::Error> {
+        let config = &options.config;
+        let mut pager = Pager {
+            measure: Measure::new(config)?,
+            size: options.page_size.dimensions(),
+            margin: options.page_size.margin(),
+            y: 0.0,
+            pages: Vec::new(),
+        };
+        let line = config.font_size;
+        for (recipe, tree) in recipes.iter() {
+            // every recipe starts on a fresh page
+            pager.new_page();
+            pager.paragraph(&state[recipe.name], Style::Title, 0.0)?;
+            if let Some(servings) = recipe.servings {
+                pager.paragraph(&state[servings], Style::Seasoning, 0.0)?;
+            }
+            pager.gap(line);
+
+            for i in recipe.ingredients(state) {
+                let i = &state[i];
+                let text = match i.amount {
+                    Some(amt) => format!("• {} {}", &state[amt], &state[i.stuff]),
+                    None => format!("• {}", &state[i.stuff]),
+                };
+                pager.paragraph(&text, Style::Action, line)?;
+            }
+            pager.gap(line);
+
+            let drawing = match options.diagram {
+                Diagram::Table => table::Table::new(state, tree).drawing(config)?,
+                Diagram::Graph => graph::layout(state, tree, config)?,
+            };
+            pager.diagram(drawing);
+        }
+        Ok(Cards {
+            options: options.clone(),
+            pages: pager.pages,
+        })
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Draw every page onto a cairo context, calling `show_page`
+    /// after each one
+    pub fn draw(&self, ctx: &cairo::Context) -> Result<(), cairo::Error> {
+        for page in self.pages.iter() {
+            for item in page.iter() {
+                match item {
+                    Item::Mark(mark) => {
+                        drawing::draw_marks(ctx, &self.options.config, std::slice::from_ref(mark))?
+                    }
+                    Item::Diagram {
+                        drawing,
+                        x,
+                        y,
+                        scale,
+                        top,
+                        height,
+                    } => {
+                        ctx.save()?;
+                        ctx.rectangle(*x, *y, drawing.width() * scale, *height);
+                        ctx.clip();
+                        ctx.translate(*x, *y - top);
+                        ctx.scale(*scale, *scale);
+                        drawing.draw(ctx)?;
+                        ctx.restore()?;
+                    }
+                }
+            }
+            ctx.show_page()?;
+        }
+        Ok(())
+    }
+
+    /// Write the cards out as a PDF
+    pub fn pdf(&self, w: &mut impl io::Write) -> Result<(), cairo::IoError> {
+        let (width, height) = self.options.page_size.dimensions();
+        let surface = cairo::PdfSurface::for_stream(width, height, Vec::<u8>::new())?;
+        drawing::write_stream(&surface, w, |ctx| self.draw(ctx))
+    }
+}
@@ -1,28 +1,101 @@
+/// The start of a standalone HTML page, with `%CSS%` standing in for
+/// the theme's stylesheet
 pub const STANDALONE_HTML_HEADER: &str = "
 <!DOCTYPE html>
 <html>
-  <body>
+  <head>
+    <meta charset=\"utf-8\" />
     <style type=\"text/css\">
-      body { font-family: \"Fira Sans\", arial; }
+%CSS%
+    </style>
+  </head>
+  <body>
+";
+
+pub const STANDALONE_HTML_FOOTER: &str = "
+  </body>
+</html>
+";
+
+/// The rules every theme shares. Colors all come from custom
+/// properties, so a theme only needs to say what those are.
+pub const BASE_CSS: &str = "      body {
+        font-family: \"Fira Sans\", arial;
+        color: var(--text);
+        background-color: var(--background);
+      }
       td {
         padding: 1em;
       }
       table, td, tr {
-        border: 2px solid;
+        border: var(--border-width) solid var(--border);
         border-spacing: 0px;
       }
       .ingredient {
-        background-color: #ddd;
+        background-color: var(--ingredient-background);
       }
       .done {
-        background-color: #555;
+        background-color: var(--done-background);
       }
-      .amount { color: #555; }
-      .seasonings { color: #333; }
-    </style>
+      .amount { color: var(--muted); }
+      .sub-recipe { font-style: italic; }
+      .seasonings { color: var(--seasonings); }
+      .time { color: var(--muted); font-size: smaller; }
 ";
 
-pub const STANDALONE_HTML_FOOTER: &str = "
-  </body>
-</html>
+pub const LIGHT_CSS: &str = "      :root {
+        --text: #000;
+        --background: #fff;
+        --border: #000;
+        --border-width: 2px;
+        --ingredient-background: #ddd;
+        --done-background: #555;
+        --muted: #555;
+        --seasonings: #333;
+      }
+";
+
+pub const DARK_CSS: &str = "      :root {
+        --text: #ddd;
+        --background: #1e1e1e;
+        --border: #888;
+        --border-width: 2px;
+        --ingredient-background: #3a3a3a;
+        --done-background: #bbb;
+        --muted: #aaa;
+        --seasonings: #ccc;
+      }
+";
+
+/// For paper: no large areas of ink, and tables kept on one page
+/// where they fit
+pub const PRINT_CSS: &str = "      :root {
+        --text: #000;
+        --background: #fff;
+        --border: #000;
+        --border-width: 1px;
+        --ingredient-background: #eee;
+        --done-background: #000;
+        --muted: #333;
+        --seasonings: #000;
+      }
+      td {
+        padding: 0.4em;
+      }
+      table {
+        page-break-inside: avoid;
+      }
+";
+
+pub const HIGH_CONTRAST_CSS: &str = "      :root {
+        --text: #000;
+        --background: #fff;
+        --border: #000;
+        --border-width: 3px;
+        --ingredient-background: #ff0;
+        --done-background: #000;
+        --muted: #000;
+        --seasonings: #000;
+      }
+      .amount, .seasonings { font-weight: bold; }
 ";
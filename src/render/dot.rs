@@ -0,0 +1,264 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::checks::{Analysis, Problem};
+use crate::types::{Action, ActionStep, IngredientRef, Input, State, StringRef};
+
+type Symbol = string_interner::DefaultSymbol;
+
+const RED: &str = "color=red, fontcolor=red";
+
+/// Escape text for use inside a double-quoted DOT string
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Where a path ends up
+enum End {
+    Join(StringRef),
+    Done,
+    /// the path doesn't lead anywhere, which is a problem
+    Nowhere,
+}
+
+/// Writes out a single recipe as a DOT cluster. Node names are
+/// prefixed with the recipe's position in the file so that several
+/// recipes can share one graph.
+struct Cluster<'a> {
+    state: &'a State,
+    prefix: String,
+    buf: String,
+    next_id: usize,
+    joins: BTreeMap<Symbol, String>,
+    done: Option<String>,
+    /// join points that are part of some problem
+    bad_joins: BTreeSet<Symbol>,
+    /// join points that are part of a cycle
+    cycle: BTreeSet<Symbol>,
+    /// `@recipe` ingredients that don't exist or form a cycle
+    bad_ingredients: BTreeSet<StringRef>,
+    bad_done: bool,
+}
+
+impl<'a> Cluster<'a> {
+    fn node(&mut self, label: &str, attrs: &str) -> String {
+        let id = format!("{}_{}", self.prefix, self.next_id);
+        self.next_id += 1;
+        self.buf.push_str(&format!(
+            "    {} [label=\"{}\", {}];\n",
+            id,
+            escape(label),
+            attrs
+        ));
+        id
+    }
+
+    fn edge(&mut self, from: &str, to: &str, bad: bool) {
+        if bad {
+            self.buf
+                .push_str(&format!("    {} -> {} [color=red];\n", from, to));
+        } else {
+            self.buf.push_str(&format!("    {} -> {};\n", from, to));
+        }
+    }
+
+    fn join(&mut self, point: StringRef) -> String {
+        if let Some(id) = self.joins.get(&point.value) {
+            return id.clone();
+        }
+        let attrs = if self.bad_joins.contains(&point.value) {
+            format!("shape=diamond, {}", RED)
+        } else {
+            "shape=diamond".to_string()
+        };
+        let state = self.state;
+        let id = self.node(&state[point], &attrs);
+        self.joins.insert(point.value, id.clone());
+        id
+    }
+
+    fn done(&mut self) -> String {
+        if let Some(id) = &self.done {
+            return id.clone();
+        }
+        let attrs = if self.bad_done {
+            format!("shape=doublecircle, {}", RED)
+        } else {
+            "shape=doublecircle".to_string()
+        };
+        let id = self.node("<>", &attrs);
+        self.done = Some(id.clone());
+        id
+    }
+
+    fn ingredient(&mut self, i: IngredientRef) -> String {
+        let i = &self.state[i];
+        let mut label = String::new();
+        if let Some(amt) = i.amount {
+            label.push_str(&format!("[{}] ", &self.state[amt]));
+        }
+        if i.sub_recipe {
+            label.push('@');
+        }
+        label.push_str(&self.state[i.stuff]);
+        let attrs = if self.bad_ingredients.contains(&i.stuff) {
+            format!("shape=plaintext, {}", RED)
+        } else {
+            "shape=plaintext".to_string()
+        };
+        self.node(&label, &attrs)
+    }
+
+    fn action(&mut self, step: &ActionStep, bad: bool) -> String {
+        let mut label = self.state[step.action].to_string();
+        if let Some(time) = step.time {
+            let passive = if time.passive { "~" } else { "" };
+            label.push_str(&format!(" [{}{}]", passive, &self.state[time.text]));
+        }
+        for (n, i) in step.seasonings.iter().enumerate() {
+            let i = &self.state[*i];
+            label.push_str(if n == 0 { "\n& " } else { ", " });
+            if let Some(amt) = i.amount {
+                label.push_str(&format!("[{}] ", &self.state[amt]));
+            }
+            label.push_str(&self.state[i.stuff]);
+        }
+        let attrs = if bad {
+            format!("shape=box, style=rounded, {}", RED)
+        } else {
+            "shape=box, style=rounded".to_string()
+        };
+        self.node(&label, &attrs)
+    }
+
+    /// Draw a path from wherever it starts, through each of its
+    /// actions, to wherever it ends
+    fn path(&mut self, start: &Input, actions: &[ActionStep], end: End, bad: bool) {
+        let mut from: Vec<String> = match start {
+            Input::Ingredients { list } => list.iter().map(|i| self.ingredient(*i)).collect(),
+            Input::Join { point } => vec![self.join(*point)],
+        };
+        for step in actions.iter() {
+            let to = self.action(step, bad);
+            for f in from.iter() {
+                self.edge(f, &to, bad);
+            }
+            from = vec![to];
+        }
+        let to = match end {
+            End::Join(point) => self.join(point),
+            End::Done => self.done(),
+            End::Nowhere => return,
+        };
+        for f in from.iter() {
+            self.edge(f, &to, bad);
+        }
+    }
+
+    /// Draw whatever came after a `<>`, starting from the `<>` itself.
+    /// Any later `<>` gets a node of its own, since leading back into
+    /// the first one would look like a loop.
+    fn after_done(&mut self, rest: &[Action]) {
+        let mut from = self.done();
+        for action in rest.iter() {
+            let to = match action {
+                Action::Action { step } => self.action(step, true),
+                Action::Join { point } => self.join(*point),
+                Action::Done { .. } => self.node("<>", &format!("shape=doublecircle, {}", RED)),
+            };
+            self.edge(&from, &to, true);
+            from = to;
+        }
+    }
+}
+
+/// Write out a Graphviz DOT graph of every recipe, with ingredients as
+/// plain text, actions as boxes, join points as diamonds and `<>` as a
+/// double circle. Unlike the other renderers this works even if the
+/// recipe has problems, in which case the parts responsible are drawn
+/// in red.
+pub fn dot(state: &State, analyses: &[&Analysis]) -> String {
+    let mut buf = String::new();
+    buf.push_str("digraph recipes {\n");
+    buf.push_str("  rankdir=LR;\n");
+    for (n, analysis) in analyses.iter().enumerate() {
+        let mut cluster = Cluster {
+            state,
+            prefix: format!("r{}", n),
+            buf: String::new(),
+            next_id: 0,
+            joins: BTreeMap::new(),
+            done: None,
+            bad_joins: BTreeSet::new(),
+            cycle: BTreeSet::new(),
+            bad_ingredients: BTreeSet::new(),
+            bad_done: false,
+        };
+        for problem in analysis.problems.iter() {
+            match problem {
+                Problem::NoDone(_) | Problem::MultipleDone(_) => cluster.bad_done = true,
+                Problem::HasCycle(links) => {
+                    for link in links.iter() {
+                        cluster.cycle.insert(link.from.value);
+                        cluster.cycle.insert(link.to.value);
+                    }
+                }
+                Problem::UndefinedJoinPoint(points)
+                | Problem::UnusedJoinPoint(points)
+                | Problem::ReusedJoinPoint(points)
+                | Problem::Disconnected(points) => {
+                    cluster.bad_joins.extend(points.iter().map(|p| p.value))
+                }
+                Problem::UnknownRecipe(name) => {
+                    cluster.bad_ingredients.insert(*name);
+                }
+                Problem::RecipeCycle(refs) => cluster.bad_ingredients.extend(refs.iter()),
+                Problem::DanglingSteps(..)
+                | Problem::StepsAfterDone(..)
+                | Problem::DuplicateRecipe(..) => {}
+            }
+        }
+        cluster.bad_joins.extend(cluster.cycle.iter().copied());
+
+        for paths in analysis.map.values() {
+            for path in paths.iter() {
+                let in_cycle = match (&path.start, path.end) {
+                    (Input::Join { point }, Some(end)) => {
+                        cluster.cycle.contains(&point.value) && cluster.cycle.contains(&end.value)
+                    }
+                    _ => false,
+                };
+                let end = match path.end {
+                    Some(point) => End::Join(point),
+                    None => End::Done,
+                };
+                cluster.path(&path.start, &path.actions, end, in_cycle);
+            }
+        }
+        for problem in analysis.problems.iter() {
+            match problem {
+                Problem::DanglingSteps(actions, start) => {
+                    cluster.path(start, actions, End::Nowhere, true)
+                }
+                Problem::StepsAfterDone(_, rest) => cluster.after_done(rest),
+                // a recipe without a `<>` still gets one, so there's
+                // something to show in red
+                Problem::NoDone(_) => {
+                    cluster.done();
+                }
+                _ => {}
+            }
+        }
+
+        buf.push_str(&format!("  subgraph cluster_{} {{\n", n));
+        buf.push_str(&format!(
+            "    label=\"{}\";\n",
+            escape(&state[analysis.name])
+        ));
+        buf.push_str(&cluster.buf);
+        buf.push_str("  }\n");
+    }
+    buf.push_str("}\n");
+    buf
+}
@@ -0,0 +1,297 @@
+//! The pieces shared by everything we draw with cairo. Renderers lay
+//! out a recipe as a `Drawing`, which is a list of already-positioned
+//! `Mark`s, and that can then be written out as whichever kind of
+//! image we want.
+
+use std::io;
+
+/// The knobs for how things get drawn. Sizes are in pixels for PNG
+/// output and in points for everything else.
+#[derive(Debug, Clone)]
+pub struct RenderConfig {
+    pub font: String,
+    pub font_size: f64,
+    pub margin: f64,
+    /// horizontal room between columns of a graph, which is where the
+    /// lines joining paths together get drawn
+    pub spacing: f64,
+    /// vertical room between rows of a graph
+    pub row_gap: f64,
+    pub line_width: f64,
+}
+
+impl std::default::Default for RenderConfig {
+    fn default() -> RenderConfig {
+        RenderConfig {
+            font: "Fira Sans".to_string(),
+            font_size: 14.0,
+            margin: 24.0,
+            spacing: 64.0,
+            row_gap: 12.0,
+            line_width: 2.0,
+        }
+    }
+}
+
+/// The different kinds of text we draw
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Style {
+    Title,
+    Ingredient,
+    SubRecipe,
+    Action,
+    Seasoning,
+}
+
+pub(crate) type Color = (f64, f64, f64);
+
+pub(crate) const BLACK: Color = (0.0, 0.0, 0.0);
+
+/// Something to draw, already positioned
+#[derive(Debug, Clone)]
+pub(crate) enum Mark {
+    /// text with its top-left corner at `(x, y)`
+    Text {
+        x: f64,
+        y: f64,
+        text: String,
+        style: Style,
+    },
+    /// text that gets wrapped onto more lines if it's wider than
+    /// `width`
+    Paragraph {
+        x: f64,
+        y: f64,
+        width: f64,
+        text: String,
+        style: Style,
+    },
+    Line(Vec<(f64, f64)>),
+    Arrow((f64, f64), (f64, f64)),
+    Box {
+        x: f64,
+        y: f64,
+        w: f64,
+        h: f64,
+        fill: Color,
+        stroke: Color,
+    },
+    /// the `<>` that paths come together at, centred on `(x, y)`
+    Dot(f64, f64),
+}
+
+fn font(config: &RenderConfig, style: Style) -> pango::FontDescription {
+    let (variant, size) = match style {
+        Style::Title => ("Bold", config.font_size * 1.6),
+        Style::Ingredient => ("Bold", config.font_size),
+        Style::SubRecipe => ("Bold Italic", config.font_size),
+        Style::Action => ("", config.font_size),
+        Style::Seasoning => ("Italic", config.font_size * 0.8),
+    };
+    let mut desc = pango::FontDescription::from_string(&format!("{} {}", config.font, variant));
+    desc.set_absolute_size(size * f64::from(pango::SCALE));
+    desc
+}
+
+fn text_layout(
+    ctx: &cairo::Context,
+    config: &RenderConfig,
+    text: &str,
+    style: Style,
+) -> Result<pango::Layout, cairo::Error> {
+    // this only fails if pango can't allocate the layout at all
+    let layout = pangocairo::create_layout(ctx).ok_or(cairo::Error::NoMemory)?;
+    layout.set_font_description(Some(&font(config, style)));
+    layout.set_text(text);
+    Ok(layout)
+}
+
+fn paragraph_layout(
+    ctx: &cairo::Context,
+    config: &RenderConfig,
+    text: &str,
+    style: Style,
+    width: f64,
+) -> Result<pango::Layout, cairo::Error> {
+    let layout = text_layout(ctx, config, text, style)?;
+    layout.set_width((width * f64::from(pango::SCALE)) as i32);
+    layout.set_wrap(pango::WrapMode::WordChar);
+    Ok(layout)
+}
+
+/// Works out how much room text is going to take up. We need a cairo
+/// context for that, even though nothing gets drawn onto it.
+pub(crate) struct Measure<'a> {
+    config: &'a RenderConfig,
+    ctx: cairo::Context,
+}
+
+impl<'a> Measure<'a> {
+    pub(crate) fn new(config: &'a RenderConfig) -> Result<Measure<'a>, cairo::Error> {
+        let scratch = cairo::ImageSurface::create(cairo::Format::Rgb24, 1, 1)?;
+        Ok(Measure {
+            config,
+            ctx: cairo::Context::new(&scratch)?,
+        })
+    }
+
+    pub(crate) fn text(&self, text: &str, style: Style) -> Result<(f64, f64), cairo::Error> {
+        let (w, h) = text_layout(&self.ctx, self.config, text, style)?.pixel_size();
+        Ok((f64::from(w), f64::from(h)))
+    }
+
+    pub(crate) fn paragraph(
+        &self,
+        text: &str,
+        style: Style,
+        width: f64,
+    ) -> Result<(f64, f64), cairo::Error> {
+        let (w, h) = paragraph_layout(&self.ctx, self.config, text, style, width)?.pixel_size();
+        Ok((f64::from(w), f64::from(h)))
+    }
+}
+
+/// A laid-out picture of a recipe, ready to be drawn
+#[derive(Debug, Clone)]
+pub struct Drawing {
+    pub(crate) config: RenderConfig,
+    pub(crate) marks: Vec<Mark>,
+    pub(crate) width: f64,
+    pub(crate) height: f64,
+}
+
+impl Drawing {
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    pub fn height(&self) -> f64 {
+        self.height
+    }
+
+    /// Draw everything onto a cairo context, with the top-left corner
+    /// at the current origin
+    pub fn draw(&self, ctx: &cairo::Context) -> Result<(), cairo::Error> {
+        draw_marks(ctx, &self.config, &self.marks)
+    }
+
+    /// Draw everything as a PNG image just big enough to hold it
+    pub fn png(&self, w: &mut impl io::Write) -> Result<(), cairo::IoError> {
+        let surface = cairo::ImageSurface::create(
+            cairo::Format::Rgb24,
+            self.width as i32,
+            self.height as i32,
+        )?;
+        let ctx = cairo::Context::new(&surface)?;
+        ctx.set_source_rgb(1.0, 1.0, 1.0);
+        ctx.paint()?;
+        self.draw(&ctx)?;
+        surface.write_to_png(w)
+    }
+
+    /// Draw everything as an SVG image, which scales cleanly since
+    /// it's all shapes rather than pixels
+    pub fn svg(&self, w: &mut impl io::Write) -> Result<(), cairo::IoError> {
+        let surface = cairo::SvgSurface::for_stream(self.width, self.height, Vec::<u8>::new())?;
+        write_stream(&surface, w, |ctx| self.draw(ctx))
+    }
+}
+
+/// Draw onto a surface made with `for_stream(.., Vec::<u8>::new())`
+/// and copy what it wrote out to `w`. Cairo wants to own whatever it's
+/// writing to, so it can't write to `w` itself.
+pub(crate) fn write_stream(
+    surface: &cairo::Surface,
+    w: &mut impl io::Write,
+    draw: impl FnOnce(&cairo::Context) -> Result<(), cairo::Error>,
+) -> Result<(), cairo::IoError> {
+    let ctx = cairo::Context::new(surface)?;
+    draw(&ctx)?;
+    drop(ctx);
+    let buf = surface
+        .finish_output_stream()
+        .map_err(|err| cairo::IoError::Io(err.error))?;
+    w.write_all(
+        buf.downcast_ref::<Vec<u8>>()
+            .expect("cairo should hand back the buffer we gave it"),
+    )?;
+    Ok(())
+}
+
+/// Draw a list of marks onto a cairo context
+pub(crate) fn draw_marks(
+    ctx: &cairo::Context,
+    config: &RenderConfig,
+    marks: &[Mark],
+) -> Result<(), cairo::Error> {
+    ctx.set_line_width(config.line_width);
+    for mark in marks.iter() {
+        match mark {
+            Mark::Text { x, y, text, style } => {
+                ctx.set_source_rgb(BLACK.0, BLACK.1, BLACK.2);
+                ctx.move_to(*x, *y);
+                let layout = text_layout(ctx, config, text, *style)?;
+                pangocairo::show_layout(ctx, &layout);
+            }
+            Mark::Paragraph {
+                x,
+                y,
+                width,
+                text,
+                style,
+            } => {
+                ctx.set_source_rgb(BLACK.0, BLACK.1, BLACK.2);
+                ctx.move_to(*x, *y);
+                let layout = paragraph_layout(ctx, config, text, *style, *width)?;
+                pangocairo::show_layout(ctx, &layout);
+            }
+            Mark::Line(points) => {
+                ctx.set_source_rgb(BLACK.0, BLACK.1, BLACK.2);
+                ctx.new_path();
+                for (x, y) in points.iter() {
+                    ctx.line_to(*x, *y);
+                }
+                ctx.stroke()?;
+            }
+            Mark::Arrow((x1, y1), (x2, y2)) => {
+                let head = config.line_width * 4.0;
+                ctx.set_source_rgb(BLACK.0, BLACK.1, BLACK.2);
+                ctx.move_to(*x1, *y1);
+                ctx.line_to(x2 - head, *y2);
+                ctx.stroke()?;
+                ctx.move_to(*x2, *y2);
+                ctx.line_to(x2 - head, y2 - head / 2.0);
+                ctx.line_to(x2 - head, y2 + head / 2.0);
+                ctx.close_path();
+                ctx.fill()?;
+            }
+            Mark::Dot(x, y) => {
+                ctx.set_source_rgb(BLACK.0, BLACK.1, BLACK.2);
+                ctx.new_path();
+                ctx.arc(
+                    *x,
+                    *y,
+                    config.line_width * 3.0,
+                    0.0,
+                    2.0 * std::f64::consts::PI,
+                );
+                ctx.fill()?;
+            }
+            Mark::Box {
+                x,
+                y,
+                w,
+                h,
+                fill,
+                stroke,
+            } => {
+                ctx.rectangle(*x, *y, *w, *h);
+                ctx.set_source_rgb(fill.0, fill.1, fill.2);
+                ctx.fill_preserve()?;
+                ctx.set_source_rgb(stroke.0, stroke.1, stroke.2);
+                ctx.stroke()?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use crate::render::html::{escape, HtmlWriter};
+use crate::schedule::{Schedule, Slot};
+use crate::timing::Duration;
+use crate::types::State;
+
+const LABEL_WIDTH: u64 = 110;
+const CHART_WIDTH: u64 = 600;
+const LANE_HEIGHT: u64 = 30;
+const BAR_HEIGHT: u64 = 22;
+const AXIS_HEIGHT: u64 = 24;
+
+/// Tick spacings we're willing to use on the time axis, in minutes
+const TICK_STEPS: [u64; 8] = [1, 5, 10, 15, 30, 60, 120, 240];
+
+/// A row of the chart: either one cook, or somewhere for passive
+/// steps to sit while they get on with it by themselves
+#[derive(Debug)]
+struct Lane {
+    label: String,
+    slots: Vec<Slot>,
+}
+
+#[derive(Debug)]
+pub struct Gantt<'a> {
+    state: &'a State,
+    schedule: &'a Schedule,
+    lanes: Vec<Lane>,
+}
+
+impl<'a> Gantt<'a> {
+    pub fn new(state: &'a State, schedule: &'a Schedule) -> Gantt<'a> {
+        let mut lanes: Vec<Lane> = (0..schedule.cooks)
+            .map(|cook| Lane {
+                label: format!("cook {}", cook + 1),
+                slots: Vec::new(),
+            })
+            .collect();
+        // passive steps can overlap each other, so they get as many
+        // lanes as it takes to keep them from being drawn on top of
+        // one another
+        let mut passive: Vec<Lane> = Vec::new();
+        for slot in schedule.slots.iter() {
+            match slot.cook {
+                Some(cook) => lanes[cook].slots.push(*slot),
+                None => {
+                    let free = passive
+                        .iter_mut()
+                        .find(|lane| lane.slots.last().is_none_or(|s| s.end <= slot.start));
+                    match free {
+                        Some(lane) => lane.slots.push(*slot),
+                        None => passive.push(Lane {
+                            label: "unattended".to_string(),
+                            slots: vec![*slot],
+                        }),
+                    }
+                }
+            }
+        }
+        lanes.extend(passive);
+        Gantt {
+            state,
+            schedule,
+            lanes,
+        }
+    }
+
+    fn x(&self, time: Duration) -> u64 {
+        let total = self.schedule.total.seconds.max(1);
+        let offset = u128::from(time.seconds) * u128::from(CHART_WIDTH) / u128::from(total);
+        LABEL_WIDTH + offset as u64
+    }
+
+    /// Draw the chart as a standalone SVG image
+    pub fn svg(&self) -> String {
+        let width = LABEL_WIDTH + CHART_WIDTH + 20;
+        let height = AXIS_HEIGHT + LANE_HEIGHT * self.lanes.len() as u64;
+        let mut buf = String::new();
+        buf.push_str(&format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"sans-serif\" font-size=\"12\">\n",
+            width, height
+        ));
+
+        // the time axis, with a tick every few minutes
+        let total_minutes = self.schedule.total.seconds.div_ceil(60);
+        let step = TICK_STEPS
+            .iter()
+            .copied()
+            .find(|step| total_minutes / step <= 10)
+            .unwrap_or_else(|| total_minutes.div_ceil(10));
+        let mut minute = 0;
+        while minute <= self.schedule.total.seconds / 60 {
+            let x = self.x(Duration::from_minutes(minute));
+            buf.push_str(&format!(
+                "  <line x1=\"{x}\" y1=\"{}\" x2=\"{x}\" y2=\"{}\" stroke=\"#ccc\"/>\n",
+                AXIS_HEIGHT - 6,
+                height,
+                x = x
+            ));
+            buf.push_str(&format!(
+                "  <text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                x,
+                AXIS_HEIGHT - 10,
+                Duration::from_minutes(minute)
+            ));
+            minute += step;
+        }
+
+        for (n, lane) in self.lanes.iter().enumerate() {
+            let top = AXIS_HEIGHT + LANE_HEIGHT * n as u64;
+            let middle = top + LANE_HEIGHT / 2;
+            buf.push_str(&format!(
+                "  <text x=\"4\" y=\"{}\" dominant-baseline=\"middle\">{}</text>\n",
+                middle,
+                escape(&lane.label)
+            ));
+            for slot in lane.slots.iter() {
+                let x = self.x(slot.start);
+                // steps that take no time still get a sliver, so that
+                // they show up at all
+                let w = (self.x(slot.end) - x).max(2);
+                let fill = if slot.cook.is_some() {
+                    "#8cb4d9"
+                } else {
+                    "#d9c68c"
+                };
+                let description = escape(&self.schedule.describe(self.state, slot.task));
+                buf.push_str(&format!(
+                    "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"#333\"><title>{}: {}</title></rect>\n",
+                    x,
+                    middle - BAR_HEIGHT / 2,
+                    w,
+                    BAR_HEIGHT,
+                    fill,
+                    self.schedule.countdown(slot),
+                    description
+                ));
+                buf.push_str(&format!(
+                    "  <text x=\"{}\" y=\"{}\" dominant-baseline=\"middle\">{}</text>\n",
+                    x + 4,
+                    middle,
+                    escape(&self.state[self.schedule.tasks[slot.task].step.action])
+                ));
+            }
+        }
+        buf.push_str("</svg>\n");
+        buf
+    }
+
+    /// The chart along with a list of instructions, like "T-15m: cook
+    /// 1 starts the broth (10m)", ready to be put into a page
+    pub fn html(&self) -> String {
+        let mut w = HtmlWriter::new();
+        w.start("div", &[("class", "schedule")]).raw("\n");
+        // everything from the recipe in the SVG is already escaped
+        w.raw(&self.svg());
+        w.start("ol", &[]).raw("\n");
+        for slot in self.schedule.slots.iter() {
+            let who = match slot.cook {
+                Some(cook) => format!("cook {}", cook + 1),
+                None => "start".to_string(),
+            };
+            w.raw("  ").start("li", &[]);
+            w.element("b", &[], &self.schedule.countdown(slot));
+            w.text(&format!(
+                " {}: {}",
+                who,
+                self.schedule.describe(self.state, slot.task)
+            ));
+            w.end().raw("\n");
+        }
+        w.end().raw("\n");
+        w.end().raw("\n");
+        w.finish()
+    }
+}
@@ -1,23 +1,280 @@
-#![allow(dead_code, unused_variables)]
-use crate::checks;
+use crate::checks::{BackwardTree, State};
+use crate::render::drawing::{Color, Drawing, Mark, Measure, RenderConfig, Style};
+use crate::types::{ActionStep, IngredientRef};
 
-pub struct RenderConfig {}
+const BOX_FILL: Color = (0.95, 0.95, 0.95);
+const BOX_STROKE: Color = (0.4, 0.4, 0.4);
 
-pub struct Graph {
-    surface: cairo::ImageSurface,
-    ctx: cairo::Context,
+/// The text inside an action box, along with how much room it takes
+#[derive(Debug)]
+struct ActionLabel {
+    name: String,
+    name_size: (f64, f64),
+    seasonings: Option<(String, (f64, f64))>,
 }
 
-impl Graph {
-    pub fn new() -> Result<Graph, cairo::Error> {
-        // there's a weird thing that's going to happen here, because
-        // we don't yet know how big to make the surface. That means
-        // that we're going to draw onto a big image surface but might
-        // need to redraw if we run out of space
-        let surface = cairo::ImageSurface::create(cairo::Format::Rgb24, 2048, 2048)?;
-        let ctx = cairo::Context::new(&surface)?;
-        Ok(Graph { surface, ctx })
+impl ActionLabel {
+    fn size(&self, padding: f64) -> (f64, f64) {
+        let (mut w, mut h) = self.name_size;
+        if let Some((_, (sw, sh))) = &self.seasonings {
+            w = w.max(*sw);
+            h += sh;
+        }
+        (w + 2.0 * padding, h + padding)
+    }
+}
+
+/// Works out where everything goes, using a scratch cairo context to
+/// measure text
+struct Layout<'a> {
+    state: &'a State,
+    config: &'a RenderConfig,
+    measure: Measure<'a>,
+    /// the left edge of each column, where column 0 holds the
+    /// ingredients and column `n` holds actions `n` steps from the
+    /// left
+    columns: Vec<f64>,
+    column_widths: Vec<f64>,
+    row_height: f64,
+    marks: Vec<Mark>,
+}
+
+impl<'a> Layout<'a> {
+    fn ingredient_label(&self, i: IngredientRef) -> (String, Style) {
+        let i = &self.state[i];
+        let text = match i.amount {
+            Some(amt) => format!("{} {}", &self.state[amt], &self.state[i.stuff]),
+            None => self.state[i.stuff].to_string(),
+        };
+        let style = if i.sub_recipe {
+            Style::SubRecipe
+        } else {
+            Style::Ingredient
+        };
+        (text, style)
+    }
+
+    fn action_label(&self, step: &ActionStep) -> Result<ActionLabel, cairo::Error> {
+        let mut name = self.state[step.action].to_string();
+        if let Some(time) = step.time {
+            let passive = if time.passive { "~" } else { "" };
+            name.push_str(&format!(" ({}{})", passive, &self.state[time.text]));
+        }
+        let name_size = self.measure.text(&name, Style::Action)?;
+        let seasonings = if step.seasonings.is_empty() {
+            None
+        } else {
+            let names: Vec<String> = step
+                .seasonings
+                .iter()
+                .map(|i| self.ingredient_label(*i).0)
+                .collect();
+            let text = format!("+ {}", names.join(", "));
+            let size = self.measure.text(&text, Style::Seasoning)?;
+            Some((text, size))
+        };
+        Ok(ActionLabel {
+            name,
+            name_size,
+            seasonings,
+        })
+    }
+
+    /// Find out how wide each column needs to be and how tall the
+    /// rows are. `depth` is the column of the last action in `tree`.
+    fn measure_tree(&mut self, tree: &BackwardTree, depth: usize) -> Result<(), cairo::Error> {
+        let padding = self.config.font_size / 2.0;
+        for i in tree.ingredients.iter() {
+            let (text, style) = self.ingredient_label(*i);
+            let (w, h) = self.measure.text(&text, style)?;
+            self.column_widths[0] = self.column_widths[0].max(w);
+            self.row_height = self.row_height.max(h);
+        }
+        let first = depth + 1 - tree.actions.len();
+        for (n, step) in tree.actions.iter().enumerate() {
+            let (w, h) = self.action_label(step)?.size(padding);
+            self.column_widths[first + n] = self.column_widths[first + n].max(w);
+            self.row_height = self.row_height.max(h);
+        }
+        for path in tree.paths.iter() {
+            self.measure_tree(path, depth - tree.actions.len())?;
+        }
+        Ok(())
+    }
+
+    fn row_center(&self, row: usize) -> f64 {
+        self.config.margin + (row as f64 + 0.5) * (self.row_height + self.config.row_gap)
+    }
+
+    /// Draw lines from each of the `inputs` to `target`, bringing
+    /// them together at a single point first if there's more than one
+    fn connect(&mut self, inputs: &[(f64, f64)], target: f64, y: f64) {
+        let gap = self.config.line_width * 2.0;
+        if let [(x, input_y)] = inputs {
+            self.marks
+                .push(Mark::Arrow((*x + gap, *input_y), (target - gap, y)));
+            return;
+        }
+        let join = target - self.config.spacing / 2.0;
+        let bend = self.config.spacing / 4.0;
+        for (x, input_y) in inputs.iter() {
+            self.marks.push(Mark::Line(vec![
+                (*x + gap, *input_y),
+                (join - bend, *input_y),
+                (join, y),
+            ]));
+        }
+        self.marks.push(Mark::Arrow((join, y), (target - gap, y)));
+    }
+
+    /// Lay out a tree, starting at `row`. This returns the points that
+    /// anything using this tree should draw lines from, along with the
+    /// number of rows the tree took up. That's usually just the end of
+    /// its last action, but a tree without any actions hands over all
+    /// of its inputs to be joined up with everything else.
+    fn place(
+        &mut self,
+        tree: &BackwardTree,
+        depth: usize,
+        mut row: usize,
+    ) -> Result<(Vec<(f64, f64)>, usize), cairo::Error> {
+        let start = row;
+        let mut inputs = Vec::new();
+        for i in tree.ingredients.iter() {
+            let (text, style) = self.ingredient_label(*i);
+            let (w, h) = self.measure.text(&text, style)?;
+            let y = self.row_center(row);
+            self.marks.push(Mark::Text {
+                x: self.columns[0],
+                y: y - h / 2.0,
+                text,
+                style,
+            });
+            inputs.push((self.columns[0] + w, y));
+            row += 1;
+        }
+        for path in tree.paths.iter() {
+            let (outputs, rows) = self.place(path, depth - tree.actions.len(), row)?;
+            inputs.extend(outputs);
+            row += rows;
+        }
+        // a tree with nothing going into it still needs a row of its
+        // own to draw its actions in
+        let rows = (row - start).max(1);
+
+        let y = match (
+            inputs.iter().map(|i| i.1).reduce(f64::min),
+            inputs.iter().map(|i| i.1).reduce(f64::max),
+        ) {
+            (Some(top), Some(bottom)) => (top + bottom) / 2.0,
+            _ => self.row_center(start),
+        };
+
+        if tree.actions.is_empty() {
+            return Ok((inputs, rows));
+        }
+
+        let padding = self.config.font_size / 2.0;
+        let first = depth + 1 - tree.actions.len();
+        if !inputs.is_empty() {
+            self.connect(&inputs, self.columns[first], y);
+        }
+        let mut output = (0.0, y);
+        for (n, step) in tree.actions.iter().enumerate() {
+            let x = self.columns[first + n];
+            if n > 0 {
+                let gap = self.config.line_width * 2.0;
+                self.marks
+                    .push(Mark::Arrow((output.0 + gap, y), (x - gap, y)));
+            }
+            let label = self.action_label(step)?;
+            let (w, h) = label.size(padding);
+            let top = y - h / 2.0;
+            self.marks.push(Mark::Box {
+                x,
+                y: top,
+                w,
+                h,
+                fill: BOX_FILL,
+                stroke: BOX_STROKE,
+            });
+            self.marks.push(Mark::Text {
+                x: x + padding,
+                y: top + padding / 2.0,
+                text: label.name,
+                style: Style::Action,
+            });
+            if let Some((text, _)) = label.seasonings {
+                self.marks.push(Mark::Text {
+                    x: x + padding,
+                    y: top + padding / 2.0 + label.name_size.1,
+                    text,
+                    style: Style::Seasoning,
+                });
+            }
+            output = (x + w, y);
+        }
+        Ok((vec![output], rows))
+    }
+}
+
+/// Lay out a tree as a graph: ingredients down the left-hand side,
+/// flowing rightwards through each action until everything comes
+/// together at the end.
+///
+/// ```text
+/// [2] onions ---> chop ---\
+///                          >--> simmer
+/// [1 L] stock ------------/
+/// ```
+pub fn layout(
+    state: &State,
+    tree: &BackwardTree,
+    config: &RenderConfig,
+) -> Result<Drawing, cairo::Error> {
+    let mut layout = Layout {
+        state,
+        config,
+        measure: Measure::new(config)?,
+        columns: vec![0.0; tree.max_depth + 1],
+        column_widths: vec![0.0; tree.max_depth + 1],
+        row_height: 0.0,
+        marks: Vec::new(),
+    };
+    layout.measure_tree(tree, tree.max_depth)?;
+
+    let mut x = config.margin;
+    for (column, width) in layout.columns.iter_mut().zip(layout.column_widths.iter()) {
+        *column = x;
+        x += width + config.spacing;
+    }
+    let (outputs, rows) = layout.place(tree, tree.max_depth, 0)?;
+    // if the paths only come together at the very end, we show
+    // that with a `<>` dot for them to meet at
+    if outputs.len() > 1 {
+        let y = (outputs.iter().map(|o| o.1).fold(f64::MAX, f64::min)
+            + outputs.iter().map(|o| o.1).fold(f64::MIN, f64::max))
+            / 2.0;
+        layout.connect(&outputs, x, y);
+        layout.marks.push(Mark::Dot(x + config.line_width * 2.0, y));
     }
 
-    pub fn draw(&self, tree: &checks::BackwardTree, s: &checks::State) {}
+    // the last column might not need all of its spacing, unless
+    // its paths are joined up without an action to go into
+    let right = layout
+        .marks
+        .iter()
+        .map(|mark| match mark {
+            Mark::Box { x, w, .. } => x + w,
+            Mark::Arrow(_, (x, _)) => *x,
+            Mark::Dot(x, _) => x + config.line_width * 3.0,
+            _ => 0.0,
+        })
+        .fold(x - config.spacing, f64::max);
+    Ok(Drawing {
+        config: config.clone(),
+        marks: layout.marks,
+        width: (right + config.margin).ceil(),
+        height: (2.0 * config.margin + rows as f64 * (layout.row_height + config.row_gap)).ceil(),
+    })
 }
@@ -0,0 +1,134 @@
+//! Everything that writes HTML goes through here, so that recipe text
+//! can't end up being read as markup, however strange it is.
+
+use thiserror::Error;
+
+/// A class name given for the HTML output that we can't use, since
+/// it would need escaping to fit in a `class` attribute
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("'{0}' isn't a valid HTML class name")]
+pub struct InvalidClass(pub String);
+
+/// Make text safe to include in HTML, either between tags or in a
+/// double-quoted attribute
+pub fn escape(s: &str) -> String {
+    let mut buf = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => buf.push_str("&amp;"),
+            '<' => buf.push_str("&lt;"),
+            '>' => buf.push_str("&gt;"),
+            '"' => buf.push_str("&quot;"),
+            '\'' => buf.push_str("&#39;"),
+            c => buf.push(c),
+        }
+    }
+    buf
+}
+
+/// Whether a class name is something like `ingredient` or
+/// `sub-recipe`: ASCII letters, digits, `-` and `_`, not starting with
+/// a digit
+pub fn is_valid_class(class: &str) -> bool {
+    let mut chars = class.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '-' || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub fn check_class(class: &str) -> Result<(), InvalidClass> {
+    if is_valid_class(class) {
+        Ok(())
+    } else {
+        Err(InvalidClass(class.to_string()))
+    }
+}
+
+/// The `id` to give a recipe's heading, so that other recipes can
+/// link to it: `recipe-` followed by the name in lower case, with
+/// a `-` between each run of letters and digits
+pub fn recipe_anchor(name: &str) -> String {
+    let words: Vec<String> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+    format!("recipe-{}", words.join("-"))
+}
+
+/// The heading that tells recipes apart when more than one is in the
+/// same page
+pub fn recipe_heading(name: &str) -> String {
+    let mut w = HtmlWriter::new();
+    w.element("h2", &[("id", &recipe_anchor(name))], name);
+    w.finish()
+}
+
+/// Builds up a piece of HTML. Text and attribute values are always
+/// escaped, and tags get closed in the order they were opened, so
+/// whatever comes out is well-formed.
+#[derive(Debug, Default)]
+pub struct HtmlWriter {
+    buf: String,
+    open: Vec<&'static str>,
+}
+
+impl HtmlWriter {
+    pub fn new() -> HtmlWriter {
+        HtmlWriter::default()
+    }
+
+    /// Open a tag, which stays open until the matching `end`
+    pub fn start(&mut self, tag: &'static str, attrs: &[(&'static str, &str)]) -> &mut Self {
+        self.buf.push('<');
+        self.buf.push_str(tag);
+        for (name, value) in attrs.iter() {
+            self.buf
+                .push_str(&format!(" {}=\"{}\"", name, escape(value)));
+        }
+        self.buf.push('>');
+        self.open.push(tag);
+        self
+    }
+
+    /// Close whichever tag was opened most recently
+    pub fn end(&mut self) -> &mut Self {
+        let tag = self.open.pop().expect("there should be a tag to close");
+        self.buf.push_str(&format!("</{}>", tag));
+        self
+    }
+
+    /// A whole element with nothing but text inside
+    pub fn element(
+        &mut self,
+        tag: &'static str,
+        attrs: &[(&'static str, &str)],
+        text: &str,
+    ) -> &mut Self {
+        self.start(tag, attrs).text(text).end()
+    }
+
+    pub fn text(&mut self, text: &str) -> &mut Self {
+        self.buf.push_str(&escape(text));
+        self
+    }
+
+    /// Add markup that's already known to be well-formed, like the
+    /// output of another writer. This isn't for anything from a
+    /// recipe.
+    pub fn raw(&mut self, html: &str) -> &mut Self {
+        self.buf.push_str(html);
+        self
+    }
+
+    pub fn finish(self) -> String {
+        assert!(
+            self.open.is_empty(),
+            "every tag should be closed, but {:?} are still open",
+            self.open
+        );
+        self.buf
+    }
+}
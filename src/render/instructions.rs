@@ -0,0 +1,276 @@
+use crate::checks::BackwardTree;
+use crate::render::html::HtmlWriter;
+use crate::render::markdown::escape as escape_markdown;
+use crate::types::{ActionStep, IngredientRef, State};
+
+/// What goes into a step: either an ingredient straight from the
+/// cupboard, or whatever an earlier step made
+enum Input {
+    Ingredient(IngredientRef),
+    /// the name of an earlier result, along with the size of the
+    /// tree that made it, so we can tell which is the main thread of
+    /// the recipe
+    Result(String, usize),
+}
+
+/// Words whose past participles can't be made by just adding "ed"
+const IRREGULAR: &[(&str, &str)] = &[
+    ("beat", "beaten"),
+    ("bring", "brought"),
+    ("cut", "cut"),
+    ("freeze", "frozen"),
+    ("grind", "ground"),
+    ("let", "let"),
+    ("make", "made"),
+    ("put", "put"),
+    ("set", "set"),
+    ("shake", "shaken"),
+    ("split", "split"),
+    ("spread", "spread"),
+    ("take", "taken"),
+];
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u')
+}
+
+/// Turn the verb starting an action into the word describing what it
+/// made, like "dice" into "diced", so that later steps can refer back
+/// to "the diced zucchini". This is English-only guesswork, but it's
+/// right for the kinds of verbs recipes tend to use.
+fn participle(action: &str) -> String {
+    let verb = action
+        .split_whitespace()
+        .next()
+        .unwrap_or("")
+        .trim_end_matches(|c: char| !c.is_alphabetic())
+        .to_lowercase();
+    if let Some((_, done)) = IRREGULAR.iter().find(|(word, _)| *word == verb) {
+        return done.to_string();
+    }
+    let chars: Vec<char> = verb.chars().collect();
+    match chars.as_slice() {
+        [] => verb,
+        [.., 'e'] => format!("{}d", verb),
+        [.., c, 'y'] if !is_vowel(*c) => format!("{}ied", &verb[..verb.len() - 1]),
+        // short words ending in a consonant after a single vowel,
+        // like "chop" or "stir", double the consonant
+        [.., a, b, c]
+            if !is_vowel(*a)
+                && is_vowel(*b)
+                && !is_vowel(*c)
+                && !matches!(c, 'w' | 'x' | 'y')
+                && chars.iter().filter(|c| is_vowel(**c)).count() == 1 =>
+        {
+            format!("{}{}ed", verb, c)
+        }
+        _ => format!("{}ed", verb),
+    }
+}
+
+/// Join a list up the way you would in a sentence, like "a, b and c"
+fn sentence_list(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [one] => one.clone(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+struct Writer<'a> {
+    state: &'a State,
+    steps: Vec<String>,
+}
+
+impl<'a> Writer<'a> {
+    fn ingredient(&self, i: IngredientRef) -> String {
+        let i = &self.state[i];
+        match i.amount {
+            Some(amt) => format!("{} {}", &self.state[amt], &self.state[i.stuff]),
+            None => self.state[i.stuff].to_string(),
+        }
+    }
+
+    fn input(&self, input: &Input) -> String {
+        match input {
+            Input::Ingredient(i) => self.ingredient(*i),
+            Input::Result(name, _) => format!("the {}", name),
+        }
+    }
+
+    /// Describe an action, with whatever it's being done to right
+    /// after it and then any seasonings and how long it takes
+    fn action(&self, step: &ActionStep, object: Option<&str>) -> String {
+        let mut buf = self.state[step.action].to_string();
+        if let Some(object) = object {
+            buf.push(' ');
+            buf.push_str(object);
+        }
+        if !step.seasonings.is_empty() {
+            let seasonings: Vec<String> = step
+                .seasonings
+                .iter()
+                .map(|i| self.ingredient(*i))
+                .collect();
+            buf.push_str(" with ");
+            buf.push_str(&sentence_list(&seasonings));
+        }
+        if let Some(time) = step.time {
+            let passive = if time.passive { "~" } else { "" };
+            buf.push_str(&format!(" ({}{})", passive, &self.state[time.text]));
+        }
+        buf
+    }
+
+    /// The first step of a path, which also has to say what goes into
+    /// it
+    fn first_step(&self, inputs: &[Input], step: &ActionStep) -> String {
+        let one_word = !self.state[step.action].contains(char::is_whitespace);
+        let base = inputs
+            .iter()
+            .enumerate()
+            .filter_map(|(n, input)| match input {
+                Input::Result(_, size) => Some((n, *size)),
+                Input::Ingredient(_) => None,
+            })
+            // the biggest earlier result is the one everything else
+            // gets added to, and if there's a tie it's the first
+            .fold(None, |best: Option<(usize, usize)>, (n, size)| match best {
+                Some((_, best_size)) if best_size >= size => best,
+                _ => Some((n, size)),
+            });
+        match (inputs, base) {
+            ([], _) => self.action(step, None),
+            ([input], _) if one_word => self.action(step, Some(&self.input(input))),
+            ([input], _) => format!("take {} and {}", self.input(input), self.action(step, None)),
+            (_, Some((base, _))) => {
+                let others: Vec<String> = inputs
+                    .iter()
+                    .enumerate()
+                    .filter(|(n, _)| *n != base)
+                    .map(|(_, input)| self.input(input))
+                    .collect();
+                format!(
+                    "add {} to {} and {}",
+                    sentence_list(&others),
+                    self.input(&inputs[base]),
+                    self.action(step, None)
+                )
+            }
+            (_, None) => {
+                let all: Vec<String> = inputs.iter().map(|input| self.input(input)).collect();
+                format!(
+                    "combine {} and {}",
+                    sentence_list(&all),
+                    self.action(step, None)
+                )
+            }
+        }
+    }
+
+    /// Write out the steps for everything a tree needs before its own
+    /// actions, and then those, returning what comes out at the end.
+    /// Paths without any actions just hand their ingredients on to
+    /// whatever step uses them.
+    fn tree(&mut self, tree: &BackwardTree) -> Vec<Input> {
+        let mut inputs: Vec<Input> = tree
+            .ingredients
+            .iter()
+            .map(|i| Input::Ingredient(*i))
+            .collect();
+        for path in tree.paths.iter() {
+            inputs.extend(self.tree(path));
+        }
+        let (first, rest) = match tree.actions.split_first() {
+            Some(actions) => actions,
+            None => return inputs,
+        };
+        self.steps.push(self.first_step(&inputs, first));
+        for step in rest.iter() {
+            self.steps.push(self.action(step, None));
+        }
+
+        let names: Vec<String> = tree
+            .ingredients
+            .iter()
+            .map(|i| self.state[self.state[*i].stuff].to_string())
+            .collect();
+        let thing = if names.is_empty() {
+            "mixture".to_string()
+        } else {
+            sentence_list(&names)
+        };
+        let last = &self.state[tree.actions[tree.actions.len() - 1].action];
+        vec![Input::Result(
+            format!("{} {}", participle(last), thing),
+            tree.size,
+        )]
+    }
+}
+
+/// A recipe written out as numbered steps, in an order where
+/// everything a step needs has already been made by the time it comes
+/// up
+#[derive(Debug)]
+pub struct Instructions {
+    steps: Vec<String>,
+}
+
+impl Instructions {
+    pub fn new(state: &State, tree: &BackwardTree) -> Instructions {
+        let mut writer = Writer {
+            state,
+            steps: Vec::new(),
+        };
+        let results = writer.tree(tree);
+        // several things coming together at the `<>` still need to be
+        // put together at the end
+        if results.len() > 1 {
+            let all: Vec<String> = results.iter().map(|input| writer.input(input)).collect();
+            writer
+                .steps
+                .push(format!("combine {}", sentence_list(&all)));
+        }
+        Instructions {
+            steps: writer
+                .steps
+                .iter()
+                .map(|step| format!("{}.", capitalize(step)))
+                .collect(),
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let mut buf = String::new();
+        for (n, step) in self.steps.iter().enumerate() {
+            buf.push_str(&format!("{}. {}\n", n + 1, step));
+        }
+        buf
+    }
+
+    pub fn markdown(&self) -> String {
+        let mut buf = String::new();
+        for (n, step) in self.steps.iter().enumerate() {
+            buf.push_str(&format!("{}. {}\n", n + 1, escape_markdown(step)));
+        }
+        buf
+    }
+
+    pub fn html(&self) -> String {
+        let mut w = HtmlWriter::new();
+        w.start("ol", &[]).raw("\n");
+        for step in self.steps.iter() {
+            w.raw("  ").element("li", &[], step).raw("\n");
+        }
+        w.end().raw("\n");
+        w.finish()
+    }
+}
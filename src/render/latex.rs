@@ -0,0 +1,228 @@
+use crate::checks::BackwardTree;
+use crate::render::table::Table;
+use crate::types::{ActionStep, IngredientRef, Recipe, State};
+
+/// How far apart columns and rows of the TikZ graph are, in cm
+const COLUMN_WIDTH: f64 = 3.5;
+const ROW_HEIGHT: f64 = 1.2;
+
+const STANDALONE_HEADER: &str = "\\documentclass{article}
+\\usepackage[T1]{fontenc}
+\\usepackage{multirow}
+\\usepackage{tikz}
+\\begin{document}
+";
+
+const STANDALONE_FOOTER: &str = "\\end{document}\n";
+
+#[derive(Debug, Default)]
+pub struct LatexOptions {
+    /// write a whole document, rather than something to `\input`
+    /// into one
+    pub standalone: bool,
+    /// draw a TikZ graph of each recipe after its table
+    pub graph: bool,
+}
+
+/// Make text safe to include in LaTeX, where most punctuation that
+/// isn't a letter or a number means something
+pub(crate) fn escape(s: &str) -> String {
+    let mut buf = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => buf.push_str("\\textbackslash{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                buf.push('\\');
+                buf.push(c);
+            }
+            '~' => buf.push_str("\\textasciitilde{}"),
+            '^' => buf.push_str("\\textasciicircum{}"),
+            '<' => buf.push_str("\\textless{}"),
+            '>' => buf.push_str("\\textgreater{}"),
+            c => buf.push(c),
+        }
+    }
+    buf
+}
+
+/// Format a TikZ coordinate, making sure anything that rounds to zero
+/// doesn't come out as `-0.00`
+fn coordinate(v: f64) -> String {
+    if v.abs() < 0.005 {
+        "0.00".to_string()
+    } else {
+        format!("{:.2}", v)
+    }
+}
+
+/// Writes out a tree as TikZ nodes, laid out the same way as the
+/// cairo graph: ingredients down the left, and each action in the
+/// column for how many steps it is from the left
+struct Graph<'a> {
+    state: &'a State,
+    buf: String,
+    next_id: usize,
+}
+
+impl<'a> Graph<'a> {
+    fn node(&mut self, style: &str, column: usize, y: f64, label: &str) -> String {
+        let id = format!("n{}", self.next_id);
+        self.next_id += 1;
+        self.buf.push_str(&format!(
+            "  \\node[{}] ({}) at ({}, {}) {{{}}};\n",
+            style,
+            id,
+            coordinate(column as f64 * COLUMN_WIDTH),
+            coordinate(y),
+            label
+        ));
+        id
+    }
+
+    fn ingredient(&self, i: IngredientRef) -> String {
+        let i = &self.state[i];
+        match i.amount {
+            Some(amt) => format!(
+                "{} {}",
+                escape(&self.state[amt]),
+                escape(&self.state[i.stuff])
+            ),
+            None => escape(&self.state[i.stuff]),
+        }
+    }
+
+    fn action_label(&self, step: &ActionStep) -> String {
+        let mut label = escape(&self.state[step.action]);
+        if let Some(time) = step.time {
+            let passive = if time.passive { "~" } else { "" };
+            label.push_str(&escape(&format!(
+                " ({}{})",
+                passive, &self.state[time.text]
+            )));
+        }
+        if !step.seasonings.is_empty() {
+            let names: Vec<String> = step
+                .seasonings
+                .iter()
+                .map(|i| self.ingredient(*i))
+                .collect();
+            label.push_str(&format!("\\\\ \\textit{{+ {}}}", names.join(", ")));
+        }
+        label
+    }
+
+    /// Lay out a tree starting at `row`, returning the nodes and rows
+    /// that anything using this tree should draw arrows from, along
+    /// with how many rows the tree took up
+    fn place(
+        &mut self,
+        tree: &BackwardTree,
+        depth: usize,
+        mut row: usize,
+    ) -> (Vec<(String, f64)>, usize) {
+        let start = row;
+        let mut inputs = Vec::new();
+        for i in tree.ingredients.iter() {
+            let y = -(row as f64) * ROW_HEIGHT;
+            let style = if self.state[*i].sub_recipe {
+                "sub recipe"
+            } else {
+                "ingredient"
+            };
+            let label = self.ingredient(*i);
+            inputs.push((self.node(style, 0, y, &label), y));
+            row += 1;
+        }
+        for path in tree.paths.iter() {
+            let (outputs, rows) = self.place(path, depth - tree.actions.len(), row);
+            inputs.extend(outputs);
+            row += rows;
+        }
+        let rows = (row - start).max(1);
+        if tree.actions.is_empty() {
+            return (inputs, rows);
+        }
+
+        let y = match (
+            inputs.iter().map(|i| i.1).reduce(f64::max),
+            inputs.iter().map(|i| i.1).reduce(f64::min),
+        ) {
+            (Some(top), Some(bottom)) => (top + bottom) / 2.0,
+            _ => -(start as f64) * ROW_HEIGHT,
+        };
+        let first = depth + 1 - tree.actions.len();
+        let mut from: Vec<String> = inputs.into_iter().map(|(id, _)| id).collect();
+        for (n, step) in tree.actions.iter().enumerate() {
+            let label = self.action_label(step);
+            let id = self.node("action", first + n, y, &label);
+            for f in from.iter() {
+                self.buf
+                    .push_str(&format!("  \\draw[->] ({}.east) -- ({}.west);\n", f, id));
+            }
+            from = vec![id];
+        }
+        (vec![(from.remove(0), y)], rows)
+    }
+}
+
+/// Draw a tree as a TikZ picture
+pub fn tikz(state: &State, tree: &BackwardTree) -> String {
+    let mut graph = Graph {
+        state,
+        buf: String::new(),
+        next_id: 0,
+    };
+    let (outputs, _) = graph.place(tree, tree.max_depth, 0);
+    // everything ends up at a `<>` dot, which is also where paths
+    // that only come together at the very end meet
+    let y = (outputs.iter().map(|o| o.1).fold(f64::MIN, f64::max)
+        + outputs.iter().map(|o| o.1).fold(f64::MAX, f64::min))
+        / 2.0;
+    let done = graph.node("done", tree.max_depth + 1, y, "");
+    for (id, _) in outputs.iter() {
+        graph
+            .buf
+            .push_str(&format!("  \\draw[->] ({}.east) -- ({});\n", id, done));
+    }
+
+    let mut buf = String::new();
+    buf.push_str("\\begin{tikzpicture}[\n");
+    buf.push_str("    >=stealth,\n");
+    buf.push_str("    ingredient/.style={anchor=west, text width=2.8cm, font=\\bfseries},\n");
+    buf.push_str("    sub recipe/.style={ingredient, font=\\bfseries\\itshape},\n");
+    buf.push_str("    action/.style={anchor=west, text width=2.8cm, draw, rounded corners},\n");
+    buf.push_str("    done/.style={circle, fill, inner sep=2pt},\n");
+    buf.push_str("  ]\n");
+    buf.push_str(&graph.buf);
+    buf.push_str("\\end{tikzpicture}\n");
+    buf
+}
+
+/// Write out recipes as LaTeX, with a section for each one holding
+/// its table and, if asked for, a graph. Anything other than a
+/// standalone document needs the `multirow` package, as well as
+/// `tikz` for the graphs.
+pub fn latex(state: &State, recipes: &[(&Recipe, &BackwardTree)], opts: &LatexOptions) -> String {
+    let mut buf = String::new();
+    if opts.standalone {
+        buf.push_str(STANDALONE_HEADER);
+    }
+    for (n, (recipe, tree)) in recipes.iter().enumerate() {
+        if n > 0 {
+            buf.push('\n');
+        }
+        buf.push_str(&format!("\\section*{{{}}}\n", escape(&state[recipe.name])));
+        if let Some(servings) = recipe.servings {
+            buf.push_str(&format!("\\textit{{{}}}\n\n", escape(&state[servings])));
+        }
+        buf.push_str(&Table::new(state, tree).latex());
+        if opts.graph {
+            buf.push('\n');
+            buf.push_str(&tikz(state, tree));
+        }
+    }
+    if opts.standalone {
+        buf.push_str(STANDALONE_FOOTER);
+    }
+    buf
+}
@@ -0,0 +1,132 @@
+use crate::checks::BackwardTree;
+use crate::quantity::Quantity;
+use crate::render::instructions::Instructions;
+use crate::render::mermaid::{self, MermaidOptions};
+use crate::render::table::{HTMLTableOptions, Table};
+use crate::shopping;
+use crate::types::{Recipe, State};
+
+/// A picture of the recipe to put after the method
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Embed {
+    /// the HTML table, which most Markdown renderers pass through
+    Table,
+    /// a Mermaid flowchart in a code block
+    Mermaid,
+}
+
+#[derive(Debug, Default)]
+pub struct MarkdownOptions {
+    pub embed: Option<Embed>,
+}
+
+/// Make text safe to include in Markdown, where it would otherwise be
+/// read as formatting
+pub(crate) fn escape(s: &str) -> String {
+    let mut buf = String::new();
+    for c in s.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '#') {
+            buf.push('\\');
+        }
+        buf.push(c);
+    }
+    buf
+}
+
+/// An amount in the ingredient list, kept as it was written unless
+/// it's had another amount of the same ingredient added to it
+struct Amount {
+    quantity: Option<Quantity>,
+    text: String,
+}
+
+/// The ingredient list for a recipe, in the order the recipe uses
+/// them, with an ingredient used more than once listed once and its
+/// amounts added up where the units allow
+fn ingredient_list(state: &State, recipe: &Recipe) -> Vec<(String, Vec<Amount>)> {
+    let mut list: Vec<(String, Vec<Amount>)> = Vec::new();
+    for i in recipe.ingredients(state) {
+        let i = &state[i];
+        let name = &state[i.stuff];
+        let amounts = match list.iter().position(|(n, _)| shopping::same_name(n, name)) {
+            Some(idx) => &mut list[idx].1,
+            None => {
+                list.push((name.to_string(), Vec::new()));
+                &mut list.last_mut().unwrap().1
+            }
+        };
+        let amt = match i.amount {
+            Some(amt) => amt,
+            None => continue,
+        };
+        if let Some(quantity) = &i.quantity {
+            let sum = amounts.iter_mut().find_map(|a| {
+                let sum = shopping::add_quantities(a.quantity.as_ref()?, quantity)?;
+                Some((a, sum))
+            });
+            if let Some((a, sum)) = sum {
+                a.text = sum.to_string();
+                a.quantity = Some(sum);
+                continue;
+            }
+        }
+        amounts.push(Amount {
+            quantity: i.quantity.clone(),
+            text: state[amt].to_string(),
+        });
+    }
+    list
+}
+
+/// Write out recipes as a Markdown document, with a section for each
+/// one giving what it makes, its ingredients and the method. The
+/// output only depends on the recipes, so it's fine to check it in
+/// and regenerate it whenever they change.
+pub fn markdown(
+    state: &State,
+    recipes: &[(&Recipe, &BackwardTree)],
+    opts: &MarkdownOptions,
+) -> String {
+    let mut sections = Vec::new();
+    for (recipe, tree) in recipes.iter() {
+        let mut buf = String::new();
+        buf.push_str(&format!("# {}\n\n", escape(&state[recipe.name])));
+        if let Some(servings) = recipe.servings {
+            buf.push_str(&format!("_{}_\n\n", escape(&state[servings])));
+        }
+
+        buf.push_str("## Ingredients\n\n");
+        for (name, amounts) in ingredient_list(state, recipe) {
+            if amounts.is_empty() {
+                buf.push_str(&format!("- {}\n", escape(&name)));
+            } else {
+                let amounts: Vec<String> = amounts.iter().map(|a| escape(&a.text)).collect();
+                buf.push_str(&format!("- {} {}\n", amounts.join(" + "), escape(&name)));
+            }
+        }
+
+        buf.push_str("\n## Method\n\n");
+        buf.push_str(&Instructions::new(state, tree).markdown());
+
+        match opts.embed {
+            Some(Embed::Table) => {
+                buf.push('\n');
+                let table = Table::new(state, tree)
+                    .html(&HTMLTableOptions::default())
+                    .expect("the default class names should all be valid");
+                buf.push_str(&table);
+            }
+            Some(Embed::Mermaid) => {
+                buf.push('\n');
+                buf.push_str(&mermaid::mermaid(
+                    state,
+                    &[(*recipe, *tree)],
+                    &MermaidOptions { code_fence: true },
+                ));
+            }
+            None => {}
+        }
+        sections.push(buf);
+    }
+    sections.join("\n")
+}
@@ -0,0 +1,142 @@
+use crate::checks::BackwardTree;
+use crate::types::{ActionStep, IngredientRef, Recipe, State};
+
+#[derive(Debug, Default)]
+pub struct MermaidOptions {
+    /// wrap the chart in a ```` ```mermaid ```` block, so it can be
+    /// pasted straight into Markdown
+    pub code_fence: bool,
+}
+
+/// Make text safe to put inside a quoted Mermaid label. Mermaid
+/// doesn't have backslash escapes, but it does understand entity
+/// codes like `#quot;`.
+fn escape(s: &str) -> String {
+    let mut buf = String::new();
+    for c in s.chars() {
+        match c {
+            '#' => buf.push_str("#35;"),
+            '"' => buf.push_str("#quot;"),
+            '<' => buf.push_str("#lt;"),
+            '>' => buf.push_str("#gt;"),
+            '\n' => buf.push_str("<br>"),
+            c => buf.push(c),
+        }
+    }
+    buf
+}
+
+struct Chart<'a> {
+    state: &'a State,
+    prefix: String,
+    buf: String,
+    next_id: usize,
+}
+
+impl<'a> Chart<'a> {
+    /// Add a node, where `open` and `close` give its shape, like `["`
+    /// and `"]` for a box
+    fn node(&mut self, label: &str, open: &str, close: &str) -> String {
+        let id = format!("{}_{}", self.prefix, self.next_id);
+        self.next_id += 1;
+        self.buf
+            .push_str(&format!("    {}{}{}{}\n", id, open, escape(label), close));
+        id
+    }
+
+    fn edge(&mut self, from: &str, to: &str) {
+        self.buf.push_str(&format!("    {} --> {}\n", from, to));
+    }
+
+    fn ingredient_label(&self, i: IngredientRef) -> String {
+        let i = &self.state[i];
+        let mut label = String::new();
+        if let Some(amt) = i.amount {
+            label.push_str(&format!("[{}] ", &self.state[amt]));
+        }
+        if i.sub_recipe {
+            label.push('@');
+        }
+        label.push_str(&self.state[i.stuff]);
+        label
+    }
+
+    fn action(&mut self, step: &ActionStep) -> String {
+        let mut label = self.state[step.action].to_string();
+        if let Some(time) = step.time {
+            let passive = if time.passive { "~" } else { "" };
+            label.push_str(&format!(" [{}{}]", passive, &self.state[time.text]));
+        }
+        let id = self.node(&label, "[\"", "\"]");
+        // seasonings come in from the side, rather than being part of
+        // the main flow of the recipe
+        for i in step.seasonings.iter() {
+            let label = self.ingredient_label(*i);
+            let seasoning = self.node(&label, "[/\"", "\"/]");
+            self.buf
+                .push_str(&format!("    {} -.-> {}\n", seasoning, id));
+        }
+        id
+    }
+
+    /// Add everything in a tree, returning the nodes that whatever
+    /// comes next should be joined up to
+    fn tree(&mut self, tree: &BackwardTree) -> Vec<String> {
+        let mut from = Vec::new();
+        for i in tree.ingredients.iter() {
+            let label = self.ingredient_label(*i);
+            from.push(self.node(&label, "([\"", "\"])"));
+        }
+        for path in tree.paths.iter() {
+            from.extend(self.tree(path));
+        }
+        for step in tree.actions.iter() {
+            let to = self.action(step);
+            for f in from.iter() {
+                self.edge(f, &to);
+            }
+            from = vec![to];
+        }
+        from
+    }
+}
+
+/// Write out recipes as a Mermaid flowchart, with each recipe in its
+/// own subgraph. Ingredients are rounded, actions are boxes, and
+/// seasonings are slanted boxes joined to their action with a dotted
+/// line.
+pub fn mermaid(
+    state: &State,
+    recipes: &[(&Recipe, &BackwardTree)],
+    opts: &MermaidOptions,
+) -> String {
+    let mut buf = String::new();
+    if opts.code_fence {
+        buf.push_str("```mermaid\n");
+    }
+    buf.push_str("flowchart LR\n");
+    for (n, (recipe, tree)) in recipes.iter().enumerate() {
+        let mut chart = Chart {
+            state,
+            prefix: format!("r{}", n),
+            buf: String::new(),
+            next_id: 0,
+        };
+        let from = chart.tree(tree);
+        let done = chart.node("<>", "(((\"", "\")))");
+        for f in from.iter() {
+            chart.edge(f, &done);
+        }
+        buf.push_str(&format!(
+            "  subgraph r{} [\"{}\"]\n",
+            n,
+            escape(&state[recipe.name])
+        ));
+        buf.push_str(&chart.buf);
+        buf.push_str("  end\n");
+    }
+    if opts.code_fence {
+        buf.push_str("```\n");
+    }
+    buf
+}
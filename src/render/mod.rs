@@ -1,3 +1,13 @@
+pub mod card;
 pub mod constants;
+pub mod dot;
+pub mod drawing;
+pub mod gantt;
 pub mod graph;
+pub mod html;
+pub mod instructions;
+pub mod latex;
+pub mod markdown;
+pub mod mermaid;
 pub mod table;
+pub mod theme;
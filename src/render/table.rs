@@ -1,7 +1,13 @@
 use crate::checks::BackwardTree;
 use crate::render::constants;
+use crate::render::drawing::{Color, Drawing, Mark, Measure, RenderConfig, Style, BLACK};
+use crate::render::html::{self, HtmlWriter, InvalidClass};
+use crate::render::latex;
+use crate::render::theme::Theme;
 use crate::types::{ActionStep, IngredientRef, State};
 
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
 #[derive(Debug)]
 struct Cell<'a> {
     colspan: usize,
@@ -13,6 +19,7 @@ struct Cell<'a> {
 struct CellIngredient<'a> {
     name: &'a str,
     amount: Option<&'a str>,
+    sub_recipe: bool,
 }
 
 #[derive(Debug)]
@@ -23,42 +30,87 @@ pub struct HTMLTableOptions {
     pub amount_class: String,
     pub seasonings_class: String,
     pub ingredient_class: String,
+    pub sub_recipe_class: String,
     pub action_class: String,
+    pub time_class: String,
     pub done_class: String,
+    /// Recipes with a heading in the same document, which references
+    /// to them link to. Other references aren't links, since there'd
+    /// be nothing for them to go to.
+    pub linked_recipes: Vec<String>,
 }
 
 impl std::default::Default for HTMLTableOptions {
     fn default() -> HTMLTableOptions {
         HTMLTableOptions {
             standalone: false,
-            html_header: constants::STANDALONE_HTML_HEADER.to_string(),
+            html_header: Theme::default().html_header(),
             html_footer: constants::STANDALONE_HTML_FOOTER.to_string(),
             amount_class: "amount".to_string(),
             seasonings_class: "seasonings".to_string(),
             ingredient_class: "ingredient".to_string(),
+            sub_recipe_class: "sub-recipe".to_string(),
             action_class: "action".to_string(),
+            time_class: "time".to_string(),
             done_class: "done".to_string(),
+            linked_recipes: Vec::new(),
+        }
+    }
+}
+
+impl HTMLTableOptions {
+    /// Make sure every class name can be used as one
+    pub fn validate(&self) -> Result<(), InvalidClass> {
+        for class in [
+            &self.amount_class,
+            &self.seasonings_class,
+            &self.ingredient_class,
+            &self.sub_recipe_class,
+            &self.action_class,
+            &self.time_class,
+            &self.done_class,
+        ] {
+            html::check_class(class)?;
         }
+        Ok(())
     }
 }
 
 impl<'a> CellIngredient<'a> {
-    fn html(&self, opts: &HTMLTableOptions) -> String {
+    fn html(&self, w: &mut HtmlWriter, opts: &HTMLTableOptions) {
         if let Some(amt) = self.amount {
-            format!(
-                "<span class=\"{}\">{}</span> {}",
-                opts.amount_class, amt, self.name
-            )
+            w.element("span", &[("class", &opts.amount_class)], amt);
+            w.text(" ");
+        }
+        if self.sub_recipe && opts.linked_recipes.iter().any(|r| r == self.name) {
+            w.element(
+                "a",
+                &[
+                    ("class", &opts.sub_recipe_class),
+                    ("href", &format!("#{}", html::recipe_anchor(self.name))),
+                ],
+                self.name,
+            );
+        } else if self.sub_recipe {
+            w.element("span", &[("class", &opts.sub_recipe_class)], self.name);
         } else {
-            self.name.to_string()
+            w.text(self.name);
+        }
+    }
+
+    fn text(&self) -> String {
+        match self.amount {
+            Some(amt) => format!("{} {}", amt, self.name),
+            None => self.name.to_string(),
         }
     }
 
     fn debug(&self) -> String {
+        let marker = if self.sub_recipe { "@" } else { "" };
         if let Some(amt) = self.amount {
-            format!("[{}] {}", amt, self.name)
+            format!("[{}] {}{}", amt, marker, self.name)
         } else {
-            self.name.to_string()
+            format!("{}{}", marker, self.name)
         }
     }
 }
@@ -70,6 +122,7 @@ enum CellData<'a> {
     },
     Step {
         name: &'a str,
+        time: Option<String>,
         seasonings: Vec<CellIngredient<'a>>,
     },
     Done,
@@ -79,9 +132,16 @@ impl<'a> CellData<'a> {
     fn debug(&self) -> String {
         match self {
             CellData::Done => "<>".to_string(),
-            CellData::Step { name, seasonings } => format!(
-                "{} & {}",
+            CellData::Step {
                 name,
+                time,
+                seasonings,
+            } => format!(
+                "{}{} & {}",
+                name,
+                time.as_ref()
+                    .map(|t| format!(" [{}]", t))
+                    .unwrap_or_default(),
                 seasonings
                     .iter()
                     .map(|i| i.debug())
@@ -93,27 +153,106 @@ impl<'a> CellData<'a> {
     }
 }
 
+impl<'a> CellData<'a> {
+    /// The lines of text to draw in this cell, for renderers which
+    /// can't just hand the whole thing off to a browser
+    fn lines(&self) -> Vec<(String, Style)> {
+        match self {
+            CellData::Done => Vec::new(),
+            CellData::Step {
+                name,
+                time,
+                seasonings,
+            } => {
+                let mut lines = vec![(
+                    match time {
+                        Some(time) => format!("{} ({})", name, time),
+                        None => name.to_string(),
+                    },
+                    Style::Action,
+                )];
+                if !seasonings.is_empty() {
+                    let names: Vec<String> = seasonings.iter().map(|i| i.text()).collect();
+                    lines.push((format!("+ {}", names.join(", ")), Style::Seasoning));
+                }
+                lines
+            }
+            CellData::Ingredient { i } => {
+                let style = if i.sub_recipe {
+                    Style::SubRecipe
+                } else {
+                    Style::Ingredient
+                };
+                vec![(i.text(), style)]
+            }
+        }
+    }
+
+    fn fill(&self) -> Color {
+        match self {
+            CellData::Done => (0.33, 0.33, 0.33),
+            CellData::Step { .. } => (1.0, 1.0, 1.0),
+            CellData::Ingredient { .. } => (0.87, 0.87, 0.87),
+        }
+    }
+}
+
 impl<'a> Cell<'a> {
-    fn html(&self, opts: &HTMLTableOptions) -> String {
+    fn latex(&self) -> String {
+        if let CellData::Done = self.contents {
+            return latex::escape("<>");
+        }
+        let lines: Vec<String> = self
+            .contents
+            .lines()
+            .into_iter()
+            .map(|(text, style)| {
+                let text = latex::escape(&text);
+                match style {
+                    Style::Title | Style::Ingredient => format!("\\textbf{{{}}}", text),
+                    Style::SubRecipe => format!("\\textbf{{\\textit{{{}}}}}", text),
+                    Style::Action => text,
+                    Style::Seasoning => format!("\\textit{{{}}}", text),
+                }
+            })
+            .collect();
+        match lines.as_slice() {
+            [line] => line.clone(),
+            // a tabular inside the cell is the easiest way to get
+            // several lines in it
+            _ => format!(
+                "\\begin{{tabular}}[c]{{@{{}}l@{{}}}}{}\\end{{tabular}}",
+                lines.join(" \\\\ ")
+            ),
+        }
+    }
+
+    fn html(&self, w: &mut HtmlWriter, opts: &HTMLTableOptions) {
         match &self.contents {
-            CellData::Done => "<>".to_string(),
-            CellData::Step { name, seasonings } => {
-                let mut buf = String::new();
-                buf.push_str(name);
+            CellData::Done => {
+                w.text("<>");
+            }
+            CellData::Step {
+                name,
+                time,
+                seasonings,
+            } => {
+                w.text(name);
+                if let Some(time) = time {
+                    w.text(" ");
+                    w.element("span", &[("class", &opts.time_class)], time);
+                }
                 if seasonings.is_empty() {
-                    return buf;
+                    return;
                 }
-                buf.push_str("<div class=\"");
-                buf.push_str(&opts.seasonings_class);
-                buf.push_str("\">");
+                w.start("div", &[("class", &opts.seasonings_class)]);
                 for i in seasonings.iter() {
-                    buf.push_str(&i.html(opts));
-                    buf.push(' ');
+                    i.html(w, opts);
+                    w.text(" ");
                 }
-                buf.push_str("</div>");
-                buf
+                w.end();
             }
-            CellData::Ingredient { i } => i.html(opts),
+            CellData::Ingredient { i } => i.html(w, opts),
         }
     }
 
@@ -126,6 +265,147 @@ impl<'a> Cell<'a> {
     }
 }
 
+/// How to draw a table as text
+#[derive(Debug, Clone)]
+pub struct TextTableOptions {
+    /// use box-drawing characters for the lines, rather than `+`, `-`
+    /// and `|`
+    pub unicode: bool,
+    /// color ingredients, steps and `<>` differently using ANSI escape
+    /// codes
+    pub color: bool,
+    /// how many columns of text fit on a line before it wraps, where
+    /// wide characters like `日` take up two
+    pub wrap_width: usize,
+}
+
+impl std::default::Default for TextTableOptions {
+    fn default() -> TextTableOptions {
+        TextTableOptions {
+            unicode: true,
+            color: false,
+            wrap_width: 24,
+        }
+    }
+}
+
+const INGREDIENT_COLOR: &str = "\x1b[32m";
+const SUB_RECIPE_COLOR: &str = "\x1b[36m";
+const ACTION_COLOR: &str = "\x1b[1m";
+const SEASONING_COLOR: &str = "\x1b[2;32m";
+const DONE_COLOR: &str = "\x1b[35m";
+const RESET: &str = "\x1b[0m";
+
+// the directions lines leave a point in the text table in
+const UP: u8 = 1;
+const DOWN: u8 = 2;
+const LEFT: u8 = 4;
+const RIGHT: u8 = 8;
+
+/// The character to draw where lines meet, given which directions
+/// they go off in
+fn junction(edges: u8, unicode: bool) -> char {
+    let vertical = edges & (UP | DOWN) != 0;
+    let horizontal = edges & (LEFT | RIGHT) != 0;
+    if !unicode {
+        return match (vertical, horizontal) {
+            (false, false) => ' ',
+            (true, false) => '|',
+            (false, true) => '-',
+            (true, true) => '+',
+        };
+    }
+    match (
+        edges & UP != 0,
+        edges & DOWN != 0,
+        edges & LEFT != 0,
+        edges & RIGHT != 0,
+    ) {
+        (false, false, false, false) => ' ',
+        (_, _, false, false) => '│',
+        (false, false, _, _) => '─',
+        (false, true, false, true) => '┌',
+        (false, true, true, false) => '┐',
+        (true, false, false, true) => '└',
+        (true, false, true, false) => '┘',
+        (true, true, false, true) => '├',
+        (true, true, true, false) => '┤',
+        (false, true, true, true) => '┬',
+        (true, false, true, true) => '┴',
+        (true, true, true, true) => '┼',
+    }
+}
+
+/// The byte offset of the end of the longest start of `word` that
+/// fits in `width` columns, which always includes at least the first
+/// character so that we make progress
+fn fit(word: &str, width: usize) -> usize {
+    let mut used = 0;
+    for (i, ch) in word.char_indices() {
+        used += ch.width().unwrap_or(0);
+        if used > width && i > 0 {
+            return i;
+        }
+    }
+    word.len()
+}
+
+/// Break text into lines of at most `width` columns, at spaces where
+/// possible and in the middle of words that are too long to fit on a
+/// line at all
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut len = 0;
+    for mut word in text.split_whitespace() {
+        while word.width() > width {
+            if len > 0 {
+                lines.push(std::mem::take(&mut line));
+                len = 0;
+            }
+            let end = fit(word, width);
+            lines.push(word[..end].to_string());
+            word = &word[end..];
+        }
+        if word.is_empty() {
+            continue;
+        }
+        if len > 0 && len + 1 + word.width() > width {
+            lines.push(std::mem::take(&mut line));
+            len = 0;
+        }
+        if len > 0 {
+            line.push(' ');
+            len += 1;
+        }
+        len += word.width();
+        line.push_str(word);
+    }
+    if len > 0 {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Work out how big each column (or row) of a table needs to be,
+/// given the starting column, span and size of every cell. A cell
+/// spanning several columns only gets more room if the ones it spans
+/// (plus the `gap` between each of them) don't already add up to
+/// enough, and that extra room goes to the last of them.
+fn spread(count: usize, cells: impl Iterator<Item = (usize, usize, f64)>, gap: f64) -> Vec<f64> {
+    let mut cells: Vec<(usize, usize, f64)> = cells.collect();
+    cells.sort_by_key(|(_, span, _)| *span);
+    let mut sizes = vec![0.0; count];
+    for (start, span, size) in cells.into_iter() {
+        let have = sizes[start..start + span].iter().sum::<f64>() + gap * (span - 1) as f64;
+        if size > have {
+            sizes[start + span - 1] += size - have;
+        }
+    }
+    sizes
+}
+
 #[derive(Debug)]
 pub struct Table<'a> {
     table_data: Vec<Vec<Cell<'a>>>,
@@ -138,23 +418,351 @@ impl<'a> Table<'a> {
         }
     }
 
-    pub fn html(&self, opts: &HTMLTableOptions) -> String {
-        let mut buf = String::new();
-        buf.push_str("<table>\n");
+    /// Write the table as HTML, which fails if any of the class names
+    /// we were given can't be used
+    pub fn html(&self, opts: &HTMLTableOptions) -> Result<String, InvalidClass> {
+        opts.validate()?;
+        let mut w = HtmlWriter::new();
+        w.start("table", &[]).raw("\n");
         for row in self.table_data.iter() {
-            buf.push_str("  <tr>");
+            w.raw("  ").start("tr", &[]);
             for cell in row.iter() {
-                buf.push_str(&format!(
-                    "<td class=\"{}\" rowspan=\"{}\" colspan=\"{}\">{}</td>",
-                    cell.html_class(opts),
-                    cell.rowspan,
-                    cell.colspan,
-                    cell.html(opts)
-                ));
+                w.start(
+                    "td",
+                    &[
+                        ("class", cell.html_class(opts)),
+                        ("rowspan", &cell.rowspan.to_string()),
+                        ("colspan", &cell.colspan.to_string()),
+                    ],
+                );
+                cell.html(&mut w, opts);
+                w.end();
+            }
+            w.end().raw("\n");
+        }
+        w.end().raw("\n");
+        Ok(w.finish())
+    }
+
+    /// Work out which row and column each cell starts in, along with
+    /// how many rows and columns there are altogether. Just like in
+    /// HTML, cells in each row fill in whatever space isn't already
+    /// taken up by cells from the rows above them.
+    fn placement(&self) -> (usize, usize, Vec<(usize, usize, &Cell<'a>)>) {
+        let mut taken: Vec<Vec<bool>> = Vec::new();
+        let mut placed = Vec::new();
+        for (r, row) in self.table_data.iter().enumerate() {
+            let mut c = 0;
+            for cell in row.iter() {
+                while taken.get(r).and_then(|row| row.get(c)) == Some(&true) {
+                    c += 1;
+                }
+                for spanned in taken.len()..r + cell.rowspan {
+                    taken.insert(spanned, Vec::new());
+                }
+                for row in taken[r..r + cell.rowspan].iter_mut() {
+                    if row.len() < c + cell.colspan {
+                        row.resize(c + cell.colspan, false);
+                    }
+                    for slot in row[c..c + cell.colspan].iter_mut() {
+                        *slot = true;
+                    }
+                }
+                placed.push((r, c, cell));
+                c += cell.colspan;
+            }
+        }
+        let columns = taken.iter().map(|row| row.len()).max().unwrap_or(0);
+        (taken.len(), columns, placed)
+    }
+
+    /// Lay out the table for drawing with cairo, using the same
+    /// rowspans and colspans as the HTML version
+    pub fn drawing(&self, config: &RenderConfig) -> Result<Drawing, cairo::Error> {
+        let measure = Measure::new(config)?;
+        let padding = config.font_size * 0.75;
+
+        let (rows, columns, cells) = self.placement();
+        let mut placed = Vec::new();
+        for (r, c, cell) in cells.into_iter() {
+            let mut lines = Vec::new();
+            let (mut w, mut h) = (0.0, 0.0);
+            for (text, style) in cell.contents.lines() {
+                let (lw, lh) = measure.text(&text, style)?;
+                w = f64::max(w, lw);
+                h += lh;
+                lines.push((text, style, lh));
+            }
+            placed.push((r, c, cell, lines, (w + 2.0 * padding, h + 2.0 * padding)));
+        }
+
+        let widths = spread(
+            columns,
+            placed
+                .iter()
+                .map(|(_, c, cell, _, (w, _))| (*c, cell.colspan, *w)),
+            0.0,
+        );
+        let heights = spread(
+            rows,
+            placed
+                .iter()
+                .map(|(r, _, cell, _, (_, h))| (*r, cell.rowspan, *h)),
+            0.0,
+        );
+        let offsets = |sizes: &[f64]| {
+            let mut offsets = vec![config.margin];
+            for size in sizes.iter() {
+                offsets.push(offsets.last().unwrap() + size);
+            }
+            offsets
+        };
+        let xs = offsets(&widths);
+        let ys = offsets(&heights);
+
+        let mut marks = Vec::new();
+        for (r, c, cell, lines, _) in placed.into_iter() {
+            let (x, y) = (xs[c], ys[r]);
+            let (w, h) = (xs[c + cell.colspan] - x, ys[r + cell.rowspan] - y);
+            marks.push(Mark::Box {
+                x,
+                y,
+                w,
+                h,
+                fill: cell.contents.fill(),
+                stroke: BLACK,
+            });
+            let content: f64 = lines.iter().map(|(_, _, lh)| lh).sum();
+            let mut top = y + (h - content) / 2.0;
+            for (text, style, lh) in lines.into_iter() {
+                marks.push(Mark::Text {
+                    x: x + padding,
+                    y: top,
+                    text,
+                    style,
+                });
+                top += lh;
+            }
+        }
+        Ok(Drawing {
+            config: config.clone(),
+            marks,
+            width: (xs[columns] + config.margin).ceil(),
+            height: (ys[heights.len()] + config.margin).ceil(),
+        })
+    }
+
+    /// Write the table as a LaTeX `tabular`, with `\\multirow` and
+    /// `\\multicolumn` standing in for rowspans and colspans
+    pub fn latex(&self) -> String {
+        let (rows, columns, cells) = self.placement();
+        // which cell covers each spot in the table, and which row it
+        // starts in
+        let mut grid: Vec<Vec<Option<(usize, &Cell)>>> = vec![vec![None; columns]; rows];
+        for (r, c, cell) in cells.into_iter() {
+            for row in grid[r..r + cell.rowspan].iter_mut() {
+                for slot in row[c..c + cell.colspan].iter_mut() {
+                    *slot = Some((r, cell));
+                }
+            }
+        }
+
+        let mut buf = String::new();
+        buf.push_str(&format!(
+            "\\begin{{tabular}}{{|{}}}\n",
+            "l|".repeat(columns)
+        ));
+        buf.push_str("\\hline\n");
+        for (r, row) in grid.iter().enumerate() {
+            let mut entries = Vec::new();
+            let mut c = 0;
+            while c < columns {
+                let (top, cell) = match row[c] {
+                    Some(slot) => slot,
+                    None => {
+                        entries.push(String::new());
+                        c += 1;
+                        continue;
+                    }
+                };
+                // rows that a `\\multirow` reaches down into still
+                // need an empty entry where it is
+                let mut entry = if top == r {
+                    cell.latex()
+                } else {
+                    String::new()
+                };
+                if top == r && cell.rowspan > 1 {
+                    entry = format!("\\multirow{{{}}}{{*}}{{{}}}", cell.rowspan, entry);
+                }
+                if cell.colspan > 1 {
+                    let spec = if c == 0 { "|l|" } else { "l|" };
+                    entry = format!("\\multicolumn{{{}}}{{{}}}{{{}}}", cell.colspan, spec, entry);
+                }
+                entries.push(entry);
+                c += cell.colspan;
+            }
+            buf.push_str(&format!("  {} \\\\\n", entries.join(" & ")));
+
+            // only draw a line under the cells that end in this row,
+            // and not under spots no cell reaches at all
+            let ends: Vec<bool> = row
+                .iter()
+                .map(|slot| match slot {
+                    Some((top, cell)) => top + cell.rowspan == r + 1,
+                    None => false,
+                })
+                .collect();
+            if ends.iter().all(|end| *end) {
+                buf.push_str("\\hline\n");
+                continue;
             }
-            buf.push_str("  </tr>\n");
+            let mut c = 0;
+            while c < columns {
+                if !ends[c] {
+                    c += 1;
+                    continue;
+                }
+                let start = c;
+                while c < columns && ends[c] {
+                    c += 1;
+                }
+                buf.push_str(&format!("\\cline{{{}-{}}}\n", start + 1, c));
+            }
+        }
+        buf.push_str("\\end{tabular}\n");
+        buf
+    }
+
+    /// Draw the table as text for printing in a terminal, with the
+    /// same rowspans and colspans as the HTML version
+    pub fn text(&self, opts: &TextTableOptions) -> String {
+        let (rows, columns, cells) = self.placement();
+        let mut placed = Vec::new();
+        for (r, c, cell) in cells.into_iter() {
+            let mut lines = Vec::new();
+            for (text, style) in cell.contents.lines() {
+                let color = match style {
+                    Style::Ingredient | Style::Title => INGREDIENT_COLOR,
+                    Style::SubRecipe => SUB_RECIPE_COLOR,
+                    Style::Action => ACTION_COLOR,
+                    Style::Seasoning => SEASONING_COLOR,
+                };
+                for line in wrap(&text, opts.wrap_width) {
+                    lines.push((line, color));
+                }
+            }
+            if let CellData::Done = cell.contents {
+                lines.push(("<>".to_string(), DONE_COLOR));
+            }
+            let width = lines
+                .iter()
+                .map(|(line, _)| line.width())
+                .max()
+                .unwrap_or(0);
+            // a space either side of the text
+            placed.push((r, c, cell, lines, (width + 2) as f64));
+        }
+
+        // the lines between cells are shared, so a cell spanning
+        // several columns or rows gets to use those too
+        let widths = spread(
+            columns,
+            placed
+                .iter()
+                .map(|(_, c, cell, _, w)| (*c, cell.colspan, *w)),
+            1.0,
+        );
+        let heights = spread(
+            rows,
+            placed
+                .iter()
+                .map(|(r, _, cell, lines, _)| (*r, cell.rowspan, lines.len() as f64)),
+            1.0,
+        );
+        let offsets = |sizes: &[f64]| {
+            let mut offsets = vec![0];
+            for size in sizes.iter() {
+                offsets.push(offsets.last().unwrap() + *size as usize + 1);
+            }
+            offsets
+        };
+        let xs = offsets(&widths);
+        let ys = offsets(&heights);
+
+        let width = xs[columns] + 1;
+        let height = ys[rows] + 1;
+        let mut edges = vec![vec![0u8; width]; height];
+        // each column holds a character along with any zero-width ones
+        // after it, or nothing if it's the second half of a wide one
+        let mut text: Vec<Vec<Option<(String, &str)>>> = vec![vec![None; width]; height];
+        for (r, c, cell, lines, _) in placed.into_iter() {
+            let (left, right) = (xs[c], xs[c + cell.colspan]);
+            let (top, bottom) = (ys[r], ys[r + cell.rowspan]);
+            for x in left..right {
+                for y in [top, bottom] {
+                    edges[y][x] |= RIGHT;
+                    edges[y][x + 1] |= LEFT;
+                }
+            }
+            for y in top..bottom {
+                for x in [left, right] {
+                    edges[y][x] |= DOWN;
+                    edges[y + 1][x] |= UP;
+                }
+            }
+            let first = top + 1 + (bottom - top - 1 - lines.len()) / 2;
+            for (y, (line, color)) in lines.into_iter().enumerate() {
+                let row = &mut text[first + y];
+                let mut x = left + 2;
+                for ch in line.chars() {
+                    match ch.width().unwrap_or(0) {
+                        // a zero-width character like a combining accent
+                        // goes along with whatever came before it
+                        0 => {
+                            if x > left + 2 {
+                                if let Some((s, _)) = &mut row[x - 1] {
+                                    s.push(ch);
+                                }
+                            }
+                        }
+                        w => {
+                            row[x] = Some((ch.to_string(), color));
+                            for _ in 1..w {
+                                x += 1;
+                                row[x] = Some((String::new(), color));
+                            }
+                            x += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut buf = String::new();
+        for (edges, text) in edges.iter().zip(text.iter()) {
+            let mut line = String::new();
+            let mut current = "";
+            for (edge, ch) in edges.iter().zip(text.iter()) {
+                let (ch, color) = match ch {
+                    Some((ch, color)) => (ch.clone(), *color),
+                    None => (junction(*edge, opts.unicode).to_string(), ""),
+                };
+                if opts.color && color != current {
+                    if !current.is_empty() {
+                        line.push_str(RESET);
+                    }
+                    line.push_str(color);
+                    current = color;
+                }
+                line.push_str(&ch);
+            }
+            if !current.is_empty() {
+                line.push_str(RESET);
+            }
+            buf.push_str(line.trim_end());
+            buf.push('\n');
         }
-        buf.push_str("</table\n");
         buf
     }
 
@@ -189,12 +797,17 @@ impl<'a> TableGenerator<'a> {
         CellIngredient {
             name: &self.state[i.stuff],
             amount: i.amount.map(|amt| &self.state[*amt]),
+            sub_recipe: i.sub_recipe,
         }
     }
 
     fn action_to_cell(&self, a: &ActionStep) -> CellData<'a> {
         CellData::Step {
             name: &self.state[a.action],
+            time: a.time.map(|t| {
+                let passive = if t.passive { "~" } else { "" };
+                format!("{}{}", passive, &self.state[t.text])
+            }),
             seasonings: a
                 .seasonings
                 .iter()
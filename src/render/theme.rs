@@ -0,0 +1,71 @@
+use crate::render::constants;
+
+use std::io;
+
+/// How standalone HTML pages look
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Theme {
+    /// light, unless whoever's reading has asked their browser for
+    /// dark pages
+    #[default]
+    Default,
+    Light,
+    Dark,
+    Print,
+    HighContrast,
+    /// CSS from a file, which goes after the light theme so that it
+    /// can change as little or as much as it likes
+    Custom(String),
+}
+
+/// The names of the built-in themes
+pub const THEMES: &[&str] = &["default", "light", "dark", "print", "high-contrast"];
+
+impl Theme {
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match name {
+            "default" => Some(Theme::Default),
+            "light" => Some(Theme::Light),
+            "dark" => Some(Theme::Dark),
+            "print" => Some(Theme::Print),
+            "high-contrast" => Some(Theme::HighContrast),
+            _ => None,
+        }
+    }
+
+    /// Find a built-in theme by name, or failing that, read the CSS
+    /// file at that path
+    pub fn load(name: &str) -> io::Result<Theme> {
+        match Theme::builtin(name) {
+            Some(theme) => Ok(theme),
+            None => Ok(Theme::Custom(std::fs::read_to_string(name)?)),
+        }
+    }
+
+    /// The whole stylesheet for this theme
+    pub fn css(&self) -> String {
+        let mut css = constants::BASE_CSS.to_string();
+        match self {
+            Theme::Default => {
+                css.push_str(constants::LIGHT_CSS);
+                css.push_str("      @media (prefers-color-scheme: dark) {\n");
+                css.push_str(constants::DARK_CSS);
+                css.push_str("      }\n");
+            }
+            Theme::Light => css.push_str(constants::LIGHT_CSS),
+            Theme::Dark => css.push_str(constants::DARK_CSS),
+            Theme::Print => css.push_str(constants::PRINT_CSS),
+            Theme::HighContrast => css.push_str(constants::HIGH_CONTRAST_CSS),
+            Theme::Custom(custom) => {
+                css.push_str(constants::LIGHT_CSS);
+                css.push_str(custom);
+            }
+        }
+        css
+    }
+
+    /// The start of a standalone page using this theme
+    pub fn html_header(&self) -> String {
+        constants::STANDALONE_HTML_HEADER.replace("%CSS%", self.css().trim_end())
+    }
+}
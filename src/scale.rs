@@ -0,0 +1,77 @@
+//! The `scale` module multiplies every amount in a recipe, so that a
+//! recipe written for four people can be made for six.
+
+use crate::diagnostics::{Diagnostic, Label};
+use crate::quantity::{Quantity, Rational};
+use crate::types::{Loc, Recipe, State};
+
+/// Split a servings note like `serves 4` or `makes 12 cookies` into
+/// the words before the number and the amount itself
+fn parse_servings(text: &str) -> Option<(&str, Quantity)> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let quantity = Quantity::parse(&text[start..])?;
+    Some((&text[..start], quantity))
+}
+
+/// How many servings a recipe says it makes, if it says. A recipe
+/// that makes nothing can't be scaled to make something, so a count
+/// of zero counts as not saying.
+pub fn servings(state: &State, recipe: &Recipe) -> Option<Rational> {
+    let (_, quantity) = parse_servings(&state[recipe.servings?])?;
+    Some(quantity.value.max()).filter(|n| *n > Rational::from_integer(0))
+}
+
+/// Multiply every ingredient and seasoning amount in the recipe by
+/// `factor`, along with its servings note if it has one. Amounts that
+/// we can't make sense of are left as written, and we return a
+/// warning for each of them.
+pub fn scale(state: &mut State, recipe: &mut Recipe, factor: Rational) -> Vec<Diagnostic> {
+    let mut warnings = Vec::new();
+    for i in recipe.ingredients(state) {
+        let amount = match state[i].amount {
+            Some(amount) => amount,
+            None => continue,
+        };
+        let scaled = match &state[i].quantity {
+            Some(quantity) => quantity.scale(factor),
+            None => {
+                warnings.push(
+                    Diagnostic::warning(format!(
+                        "can't scale the amount '{}', so it's been left as-is",
+                        &state[amount]
+                    ))
+                    .with_label(Label::primary(
+                        amount.l,
+                        amount.r,
+                        "not a number",
+                    )),
+                );
+                continue;
+            }
+        };
+        state.set_quantity(i, scaled);
+    }
+
+    if let Some(servings) = recipe.servings {
+        let scaled = parse_servings(&state[servings])
+            .map(|(prefix, quantity)| format!("{}{}", prefix, quantity.scale(factor)));
+        match scaled {
+            Some(scaled) => {
+                let value = state.add_string(&scaled);
+                recipe.servings = Some(Loc { value, ..servings });
+            }
+            None => warnings.push(
+                Diagnostic::warning(format!(
+                    "can't scale the servings '{}', so they've been left as-is",
+                    &state[servings]
+                ))
+                .with_label(Label::primary(
+                    servings.l,
+                    servings.r,
+                    "no number here",
+                )),
+            ),
+        }
+    }
+    warnings
+}
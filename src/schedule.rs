@@ -0,0 +1,210 @@
+//! The `schedule` module works out who should do what and when, so
+//! that several cooks can work on separate paths of a recipe at the
+//! same time.
+
+use crate::checks::BackwardTree;
+use crate::timing::Duration;
+use crate::types::{ActionStep, State};
+
+/// A single step to be scheduled, along with the steps that have to
+/// be finished before it can start
+#[derive(Debug, Clone)]
+pub struct Task {
+    pub step: ActionStep,
+    pub duration: Duration,
+    pub passive: bool,
+    pub deps: Vec<usize>,
+}
+
+/// When a task happens and who's doing it. Passive tasks like
+/// simmering don't need a cook, so `cook` is `None` for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub task: usize,
+    pub cook: Option<usize>,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+/// A complete plan for making a recipe. `slots` are sorted by when
+/// they start, and `total` is how long the whole thing takes.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    pub cooks: usize,
+    pub tasks: Vec<Task>,
+    pub slots: Vec<Slot>,
+    pub total: Duration,
+}
+
+/// Add every step in a tree to `tasks`, returning the indices of the
+/// tasks that have to be done before anything after this tree can
+/// happen
+fn flatten(tree: &BackwardTree, tasks: &mut Vec<Task>) -> Vec<usize> {
+    let mut ready: Vec<usize> = tree
+        .paths
+        .iter()
+        .flat_map(|path| flatten(path, tasks))
+        .collect();
+    for step in tree.actions.iter() {
+        let time = step.time;
+        tasks.push(Task {
+            step: step.clone(),
+            duration: time.and_then(|t| t.duration).unwrap_or_default(),
+            passive: time.is_some_and(|t| t.passive),
+            deps: ready,
+        });
+        ready = vec![tasks.len() - 1];
+    }
+    ready
+}
+
+impl Schedule {
+    /// Schedule every step in a tree across `cooks` cooks. This is a
+    /// greedy list scheduler: whenever a cook is free, they start on
+    /// whichever available step has the longest chain of steps after
+    /// it, since that's the one most likely to hold everything up.
+    /// Passive steps start as soon as they can, without a cook.
+    pub fn new(tree: &BackwardTree, cooks: usize) -> Schedule {
+        let cooks = cooks.max(1);
+        let mut tasks = Vec::new();
+        flatten(tree, &mut tasks);
+
+        // how long it takes from the start of each task to the end of
+        // the recipe, which is our priority. Dependencies always come
+        // before the tasks that need them, so we can go backwards.
+        let mut remaining: Vec<Duration> = tasks.iter().map(|t| t.duration).collect();
+        for idx in (0..tasks.len()).rev() {
+            for dep in tasks[idx].deps.iter() {
+                let through = tasks[*dep].duration + remaining[idx];
+                if through > remaining[*dep] {
+                    remaining[*dep] = through;
+                }
+            }
+        }
+
+        let mut finished_at: Vec<Option<Duration>> = vec![None; tasks.len()];
+        let mut started = vec![false; tasks.len()];
+        let mut cook_free_at = vec![Duration::default(); cooks];
+        let mut slots = Vec::new();
+        let mut now = Duration::default();
+
+        while slots.len() < tasks.len() {
+            // starting a step that takes no time can make more steps
+            // ready straight away, so keep going until nothing changes
+            let mut progress = true;
+            while progress {
+                progress = false;
+                let mut ready: Vec<usize> = (0..tasks.len())
+                    .filter(|idx| !started[*idx])
+                    .filter(|idx| {
+                        tasks[*idx]
+                            .deps
+                            .iter()
+                            .all(|dep| finished_at[*dep].is_some_and(|end| end <= now))
+                    })
+                    .collect();
+                ready.sort_by_key(|idx| (std::cmp::Reverse(remaining[*idx]), *idx));
+
+                for idx in ready {
+                    let cook = if tasks[idx].passive {
+                        None
+                    } else {
+                        match (0..cooks).find(|c| cook_free_at[*c] <= now) {
+                            Some(cook) => Some(cook),
+                            None => continue,
+                        }
+                    };
+                    let end = now + tasks[idx].duration;
+                    if let Some(cook) = cook {
+                        cook_free_at[cook] = end;
+                    }
+                    started[idx] = true;
+                    finished_at[idx] = Some(end);
+                    progress = true;
+                    slots.push(Slot {
+                        task: idx,
+                        cook,
+                        start: now,
+                        end,
+                    });
+                }
+            }
+
+            // jump ahead to the next time something finishes, since
+            // that's the only time anything new can start
+            let next = finished_at
+                .iter()
+                .flatten()
+                .chain(cook_free_at.iter())
+                .filter(|t| **t > now)
+                .min();
+            match next {
+                Some(next) => now = *next,
+                None => break,
+            }
+        }
+
+        let total = slots.iter().map(|s| s.end).max().unwrap_or_default();
+        slots.sort_by_key(|s| (s.start, s.cook.is_none(), s.cook, s.task));
+        Schedule {
+            cooks,
+            tasks,
+            slots,
+            total,
+        }
+    }
+
+    /// Describe when a slot starts as a countdown to the end of the
+    /// recipe, like `T-15m`
+    pub fn countdown(&self, slot: &Slot) -> String {
+        format!(
+            "T-{}",
+            Duration::from_seconds(self.total.seconds - slot.start.seconds)
+        )
+    }
+
+    /// A short description of a task, like `simmer (~2h)`
+    pub fn describe(&self, state: &State, task: usize) -> String {
+        let task = &self.tasks[task];
+        let name = &state[task.step.action];
+        match task.step.time {
+            Some(time) if time.duration.is_some() => {
+                let passive = if time.passive { "~" } else { "" };
+                format!("{} ({}{})", name, passive, task.duration)
+            }
+            _ => name.to_string(),
+        }
+    }
+
+    /// The schedule as a list of instructions, e.g.
+    ///
+    /// ```text
+    /// total time: 2h 26m with 2 cooks
+    /// T-2h 26m  cook 1: cube (10m)
+    /// T-2h 26m  cook 2: chop (10m)
+    /// T-2h 1m   start: simmer (~2h)
+    /// ```
+    pub fn text(&self, state: &State) -> String {
+        let plural = if self.cooks == 1 { "" } else { "s" };
+        let mut buf = format!(
+            "total time: {} with {} cook{}\n",
+            self.total, self.cooks, plural
+        );
+        let countdowns: Vec<String> = self.slots.iter().map(|s| self.countdown(s)).collect();
+        let width = countdowns.iter().map(|c| c.len()).max().unwrap_or(0);
+        for (slot, countdown) in self.slots.iter().zip(countdowns.iter()) {
+            let who = match slot.cook {
+                Some(cook) => format!("cook {}", cook + 1),
+                None => "start".to_string(),
+            };
+            buf.push_str(&format!(
+                "{:width$}  {}: {}\n",
+                countdown,
+                who,
+                self.describe(state, slot.task),
+                width = width
+            ));
+        }
+        buf
+    }
+}
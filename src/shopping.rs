@@ -0,0 +1,209 @@
+//! The `shopping` module gathers up every ingredient used by one or
+//! more recipes into a single list of things to buy.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::checks::BackwardTree;
+use crate::quantity::{Quantity, Rational, Unit};
+use crate::types::{Cookbook, Ingredient, IngredientRef, Recipe, State};
+
+/// Everything we need to buy of a single ingredient. Amounts in units
+/// that can be added together are merged, so there's one entry in
+/// `amounts` per kind of unit, e.g. `1 1/4 cups` and `2 cloves`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    pub name: String,
+    pub amounts: Vec<Quantity>,
+}
+
+/// A list of ingredients to buy. Ingredients are merged by name
+/// (ignoring case), and ingredients that never come with an amount,
+/// like `salt` or `[to taste] pepper`, are kept separately in
+/// `to_taste`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShoppingList {
+    pub items: Vec<Item>,
+    pub to_taste: Vec<String>,
+}
+
+pub(crate) fn same_name(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
+}
+
+/// Whether `plural` is `singular` with a plural ending, like `cloves`
+/// and `clove` or `bunches` and `bunch`
+fn is_plural_of(plural: &str, singular: &str) -> bool {
+    let plural = plural.trim().to_lowercase();
+    let singular = singular.trim().to_lowercase();
+    plural.strip_suffix("es") == Some(&singular) || plural.strip_suffix('s') == Some(&singular)
+}
+
+/// Try to add two quantities together, which works if they're in the
+/// same unit or in units that measure the same thing. When the units
+/// differ, the result is in whichever one is larger, so that `1 cup`
+/// and `2 tbsp` add up to `1 1/8 cups`.
+pub(crate) fn add_quantities(a: &Quantity, b: &Quantity) -> Option<Quantity> {
+    // units we don't know about still match if they're the same word,
+    // so that `1 clove` and `2 cloves` can be added together, in which
+    // case we'd rather use the plural
+    if let (Some(Unit::Other(x)), Some(Unit::Other(y))) = (&a.unit, &b.unit) {
+        let (singular, plural) = if same_name(x, y) || is_plural_of(y, x) {
+            (x, y)
+        } else if is_plural_of(x, y) {
+            (y, x)
+        } else {
+            return None;
+        };
+        let value = a.value + b.value;
+        let unit = if value.max() > Rational::from_integer(1) {
+            plural
+        } else {
+            singular
+        };
+        return Some(Quantity {
+            value,
+            unit: Some(Unit::Other(unit.clone())),
+        });
+    }
+    if a.unit == b.unit {
+        return Some(Quantity {
+            value: a.value + b.value,
+            unit: a.unit.clone(),
+        });
+    }
+    let (dim_a, per_a) = a.unit.as_ref()?.measure()?;
+    let (dim_b, per_b) = b.unit.as_ref()?.measure()?;
+    if dim_a != dim_b {
+        return None;
+    }
+    let (big, small, ratio) = if per_a >= per_b {
+        (a, b, per_b / per_a)
+    } else {
+        (b, a, per_a / per_b)
+    };
+    Some(Quantity {
+        value: big.value + small.value.scale(ratio),
+        unit: big.unit.clone(),
+    })
+}
+
+impl ShoppingList {
+    pub fn new() -> ShoppingList {
+        ShoppingList::default()
+    }
+
+    /// Add a single ingredient to the list. References to other
+    /// recipes are skipped, since those get made rather than bought.
+    pub fn add_ingredient(&mut self, state: &State, ingredient: &Ingredient) {
+        if ingredient.sub_recipe {
+            return;
+        }
+        let name = &state[ingredient.stuff];
+        let quantity = match &ingredient.quantity {
+            Some(quantity) => quantity,
+            None => {
+                let known = self.items.iter().any(|i| same_name(&i.name, name))
+                    || self.to_taste.iter().any(|i| same_name(i, name));
+                if !known {
+                    self.to_taste.push(name.to_string());
+                }
+                return;
+            }
+        };
+
+        // now that we know how much we need, it's not just a
+        // to-taste ingredient any more
+        self.to_taste.retain(|i| !same_name(i, name));
+        let item = match self.items.iter_mut().find(|i| same_name(&i.name, name)) {
+            Some(item) => item,
+            None => {
+                self.items.push(Item {
+                    name: name.to_string(),
+                    amounts: Vec::new(),
+                });
+                self.items.last_mut().unwrap()
+            }
+        };
+        for amount in item.amounts.iter_mut() {
+            if let Some(sum) = add_quantities(amount, quantity) {
+                *amount = sum;
+                return;
+            }
+        }
+        item.amounts.push(quantity.clone());
+    }
+
+    fn add_all(&mut self, state: &State, list: &[IngredientRef]) {
+        for i in list.iter() {
+            self.add_ingredient(state, &state[*i]);
+        }
+    }
+
+    /// Add every ingredient and seasoning in a recipe
+    pub fn add_recipe(&mut self, state: &State, recipe: &Recipe) {
+        self.add_all(state, &recipe.ingredients(state));
+    }
+
+    /// Add every ingredient and seasoning in a tree, which will
+    /// include the ingredients of any recipes inlined into it
+    pub fn add_tree(&mut self, state: &State, tree: &BackwardTree) {
+        self.add_all(state, &tree.ingredients);
+        for step in tree.actions.iter() {
+            self.add_all(state, &step.seasonings);
+        }
+        for path in tree.paths.iter() {
+            self.add_tree(state, path);
+        }
+    }
+
+    /// Make a list for some recipes and every recipe they use by way
+    /// of `@name`, directly or not. Each recipe only gets bought for
+    /// once, however many times it's used, so `trees` should be the
+    /// trees from before any inlining. Recipes without a tree have
+    /// their ingredients added straight from the recipe.
+    pub fn for_recipes(
+        state: &State,
+        cookbook: &Cookbook,
+        trees: &BTreeMap<string_interner::DefaultSymbol, BackwardTree>,
+        recipes: &[&Recipe],
+    ) -> ShoppingList {
+        let mut list = ShoppingList::new();
+        let mut seen = BTreeSet::new();
+        let mut todo = recipes.to_vec();
+        let mut next = 0;
+        while let Some(recipe) = todo.get(next).copied() {
+            next += 1;
+            if !seen.insert(recipe.name.value) {
+                continue;
+            }
+            match trees.get(&recipe.name.value) {
+                Some(tree) => list.add_tree(state, tree),
+                None => list.add_recipe(state, recipe),
+            }
+            for name in recipe.sub_recipes(state) {
+                todo.extend(cookbook.find_symbol(name.value));
+            }
+        }
+        list
+    }
+
+    /// Write out the list as plain text, one ingredient per line,
+    /// followed by the to-taste ingredients
+    pub fn text(&self) -> String {
+        let mut buf = String::new();
+        for item in self.items.iter() {
+            let amounts: Vec<String> = item.amounts.iter().map(|a| a.to_string()).collect();
+            buf.push_str(&format!("{} {}\n", amounts.join(" + "), item.name));
+        }
+        if !self.to_taste.is_empty() {
+            if !self.items.is_empty() {
+                buf.push('\n');
+            }
+            buf.push_str("to taste:\n");
+            for name in self.to_taste.iter() {
+                buf.push_str(&format!("{}\n", name));
+            }
+        }
+        buf
+    }
+}
@@ -0,0 +1,206 @@
+//! The `timing` module works out how long a recipe takes, using the
+//! durations attached to steps like `simmer [~2h]`.
+
+use std::fmt;
+use std::io;
+
+use crate::checks::BackwardTree;
+use crate::diagnostics::{Diagnostic, Label};
+use crate::types::{ActionStep, State};
+
+/// A length of time, to the second
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Duration {
+    pub seconds: u64,
+}
+
+impl Duration {
+    pub fn from_seconds(seconds: u64) -> Duration {
+        Duration { seconds }
+    }
+
+    pub fn from_minutes(minutes: u64) -> Duration {
+        Duration {
+            seconds: minutes.saturating_mul(60),
+        }
+    }
+
+    /// Parse a duration like `5m`, `1h 30m`, `1.5 hours` or `45 sec`.
+    /// Every number needs a unit after it, and the total has to fit
+    /// in a `u64` of seconds.
+    pub fn parse(s: &str) -> Option<Duration> {
+        let mut rest = s.trim();
+        if rest.is_empty() {
+            return None;
+        }
+        let mut seconds = 0.0;
+        while !rest.is_empty() {
+            let num_end = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let n: f64 = rest[..num_end].parse().ok()?;
+            rest = rest[num_end..].trim_start();
+            let unit_end = rest
+                .find(|c: char| !c.is_alphabetic())
+                .unwrap_or(rest.len());
+            let per_unit = match rest[..unit_end].to_lowercase().as_str() {
+                "h" | "hr" | "hrs" | "hour" | "hours" => 3600.0,
+                "m" | "min" | "mins" | "minute" | "minutes" => 60.0,
+                "s" | "sec" | "secs" | "second" | "seconds" => 1.0,
+                _ => return None,
+            };
+            seconds += n * per_unit;
+            rest = rest[unit_end..].trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        }
+        // a time too long to count in seconds is no more use to us
+        // than one we can't read at all
+        if !seconds.is_finite() || seconds.round() >= u64::MAX as f64 {
+            return None;
+        }
+        Some(Duration {
+            seconds: seconds.round() as u64,
+        })
+    }
+}
+
+impl std::ops::Add for Duration {
+    type Output = Duration;
+
+    fn add(self, other: Duration) -> Duration {
+        Duration {
+            seconds: self.seconds.saturating_add(other.seconds),
+        }
+    }
+}
+
+impl std::ops::AddAssign for Duration {
+    fn add_assign(&mut self, other: Duration) {
+        self.seconds = self.seconds.saturating_add(other.seconds);
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hours = self.seconds / 3600;
+        let minutes = self.seconds % 3600 / 60;
+        let seconds = self.seconds % 60;
+        let mut parts = Vec::new();
+        if hours > 0 {
+            parts.push(format!("{}h", hours));
+        }
+        if minutes > 0 {
+            parts.push(format!("{}m", minutes));
+        }
+        if seconds > 0 {
+            parts.push(format!("{}s", seconds));
+        }
+        if parts.is_empty() {
+            return write!(f, "0m");
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// How long a recipe takes. `active` is the time someone actually
+/// has to spend working on it, while `elapsed` is how long it takes
+/// from start to finish, assuming separate paths can happen at the
+/// same time. The `critical_path` is the chain of steps that decides
+/// the elapsed time, in the order they happen.
+#[derive(Debug, Clone)]
+pub struct Timing {
+    pub active: Duration,
+    pub elapsed: Duration,
+    pub critical_path: Vec<ActionStep>,
+}
+
+impl Timing {
+    /// Work out the timing of everything in a tree. Steps without a
+    /// duration are counted as taking no time at all.
+    pub fn from_tree(tree: &BackwardTree) -> Timing {
+        // every path into this node has to finish before its own
+        // steps can start, so it's the slowest one that matters
+        let mut active = Duration::default();
+        let mut slowest: Option<Timing> = None;
+        for path in tree.paths.iter() {
+            let timing = Timing::from_tree(path);
+            active += timing.active;
+            if slowest.as_ref().is_none_or(|s| timing.elapsed > s.elapsed) {
+                slowest = Some(timing);
+            }
+        }
+        let (mut elapsed, mut critical_path) = match slowest {
+            Some(timing) => (timing.elapsed, timing.critical_path),
+            None => (Duration::default(), Vec::new()),
+        };
+
+        for step in tree.actions.iter() {
+            if let Some(duration) = step.time.and_then(|t| t.duration) {
+                elapsed += duration;
+                if !step.time.unwrap().passive {
+                    active += duration;
+                }
+            }
+            critical_path.push(step.clone());
+        }
+
+        Timing {
+            active,
+            elapsed,
+            critical_path,
+        }
+    }
+
+    /// Print the timing, e.g.
+    ///
+    /// ```text
+    /// active time: 15m
+    /// total time: 2h 10m
+    /// critical path:
+    ///   dice (5m)
+    ///   simmer (~2h)
+    /// ```
+    pub fn debug(&self, w: &mut impl io::Write, state: &State) -> io::Result<()> {
+        writeln!(w, "active time: {}", self.active)?;
+        writeln!(w, "total time: {}", self.elapsed)?;
+        writeln!(w, "critical path:")?;
+        for step in self.critical_path.iter() {
+            write!(w, "  {}", &state[step.action])?;
+            if let Some(time) = step.time {
+                let passive = if time.passive { "~" } else { "" };
+                match time.duration {
+                    Some(duration) => write!(w, " ({}{})", passive, duration)?,
+                    None => write!(w, " ({}?)", passive)?,
+                }
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+}
+
+/// Warnings for every step in the tree whose time we couldn't read,
+/// since those will be counted as taking no time
+pub fn unreadable_times(state: &State, tree: &BackwardTree) -> Vec<Diagnostic> {
+    let mut warnings = Vec::new();
+    for path in tree.paths.iter() {
+        warnings.extend(unreadable_times(state, path));
+    }
+    for step in tree.actions.iter() {
+        if let Some(time) = step.time {
+            if time.duration.is_none() {
+                warnings.push(
+                    Diagnostic::warning(format!(
+                        "can't tell how long '{}' is, so it's been counted as no time at all",
+                        &state[time.text]
+                    ))
+                    .with_label(Label::primary(
+                        time.text.l,
+                        time.text.r,
+                        "expected something like `5m` or `1h 30m`",
+                    )),
+                );
+            }
+        }
+    }
+    warnings
+}
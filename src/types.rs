@@ -1,4 +1,6 @@
-use std::ops::{Deref, Index};
+use crate::quantity::{Quantity, Value};
+use crate::timing::Duration;
+use std::ops::{Deref, Index, IndexMut};
 use std::{fmt, io};
 
 // A wrapper struct that indicates where a given value was positioned
@@ -20,13 +22,110 @@ impl<T> Deref for Loc<T> {
 
 pub type StringRef = Loc<string_interner::DefaultSymbol>;
 
-/// A recipe as written is a name and a set of rules.
+/// Turn a quoted string from the source (including its surrounding
+/// double quotes) into the text it stands for, handling `\"` and `\\`
+/// escapes
+pub fn unquote(s: &str) -> String {
+    let inner = &s[1..s.len() - 1];
+    let mut buf = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(escaped) = chars.next() {
+                buf.push(escaped);
+            }
+        } else {
+            buf.push(c);
+        }
+    }
+    buf
+}
+
+/// The inverse of `unquote`: wrap a string in double quotes if it
+/// couldn't otherwise be read back in as plain text. This errs on the
+/// side of quoting, since quoting is always allowed.
+pub fn quote(s: &str) -> std::borrow::Cow<'_, str> {
+    let plain = |c: char| c.is_alphanumeric() || "/,_.'’()%:!?°".contains(c);
+    let chars: Vec<char> = s.chars().collect();
+    let needs_quotes = chars.is_empty()
+        || chars[0] == ' '
+        || chars[chars.len() - 1] == ' '
+        || chars.iter().enumerate().any(|(i, &c)| match c {
+            ' ' => false,
+            '-' => !chars.get(i + 1).copied().is_some_and(plain),
+            c => !plain(c),
+        });
+    if !needs_quotes {
+        return std::borrow::Cow::Borrowed(s);
+    }
+    let escaped = s.replace('\\', "\\\\").replace('"', "\\\"");
+    std::borrow::Cow::Owned(format!("\"{}\"", escaped))
+}
+
+/// A recipe as written is a name and a set of rules, along with an
+/// optional note about how much it makes, like `[serves 4]`.
 #[derive(Debug)]
 pub struct Recipe {
     pub name: StringRef,
+    pub servings: Option<StringRef>,
     pub rules: Vec<RuleRef>,
 }
 
+/// A cookbook is every recipe in a single file. All of them share a
+/// single `State`.
+#[derive(Debug)]
+pub struct Cookbook {
+    pub recipes: Vec<Recipe>,
+}
+
+impl Cookbook {
+    /// Find the first recipe with the given name
+    pub fn find(&self, state: &State, name: &str) -> Option<&Recipe> {
+        Some(&self.recipes[self.position(state, name)?])
+    }
+
+    /// Find the index of the first recipe with the given name
+    pub fn position(&self, state: &State, name: &str) -> Option<usize> {
+        let name = state.lookup_string(name)?;
+        self.recipes.iter().position(|r| *r.name == name)
+    }
+
+    /// Find the first recipe whose name is the given interned string
+    pub fn find_symbol(&self, name: string_interner::DefaultSymbol) -> Option<&Recipe> {
+        self.recipes.iter().find(|r| *r.name == name)
+    }
+}
+
+impl Recipe {
+    /// Every ingredient in this recipe, including seasonings, in
+    /// the order they appear
+    pub fn ingredients(&self, state: &State) -> Vec<IngredientRef> {
+        let mut list = Vec::new();
+        for rule in self.rules.iter() {
+            let rule = &state[*rule];
+            if let Input::Ingredients { list: inputs } = &rule.input {
+                list.extend(inputs.iter().copied());
+            }
+            for action in rule.actions.iter() {
+                if let Action::Action { step } = action {
+                    list.extend(step.seasonings.iter().copied());
+                }
+            }
+        }
+        list
+    }
+
+    /// Every ingredient in this recipe which refers to another
+    /// recipe, in the order they appear
+    pub fn sub_recipes(&self, state: &State) -> Vec<StringRef> {
+        self.ingredients(state)
+            .into_iter()
+            .filter(|i| state[*i].sub_recipe)
+            .map(|i| state[i].stuff)
+            .collect()
+    }
+}
+
 /// A rule starts from an input and includes a sequence of actions
 /// afterwards. No invariant-checking has been performed on values of
 /// type `Rule`, so it's possible for it to represent recipes which
@@ -38,20 +137,32 @@ pub struct Rule {
 }
 
 /// Each step of an action consists of the thing being done along with
-/// an optional set of added ingredients
+/// an optional time it takes and an optional set of added ingredients
 #[derive(Debug, Clone)]
 pub struct ActionStep {
     pub action: StringRef,
+    pub time: Option<StepTime>,
     pub seasonings: Vec<IngredientRef>,
 }
 
+/// How long a step takes, written like `[5m]`, or `[~2h]` for a
+/// passive step (like simmering or resting) that doesn't need anyone
+/// to be working on it. As with amounts, `text` is kept as written
+/// and `duration` is only there if we could make sense of it.
+#[derive(Debug, Copy, Clone)]
+pub struct StepTime {
+    pub text: StringRef,
+    pub passive: bool,
+    pub duration: Option<Duration>,
+}
+
 /// A step can be one of three things: an action, a join point, or the
 /// special `<>` symbol to represent a finished recipe.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Action {
     Action { step: ActionStep },
     Join { point: StringRef },
-    Done,
+    Done { at: Loc<()> },
 }
 
 /// The start of a rule can be either a list of ingredients or a join
@@ -63,11 +174,19 @@ pub enum Input {
 }
 
 /// An ingredient is an optional specified amount as well as the name
-/// of the ingredient
+/// of the ingredient. An ingredient written as `@name` is instead a
+/// reference to another recipe in the same cookbook, in which case
+/// `sub_recipe` is set and `stuff` is the name of that recipe.
+///
+/// If we could make sense of the amount, `quantity` holds the
+/// structured version of it, but `amount` is still kept around so we
+/// can show it exactly as it was written.
 #[derive(Debug)]
 pub struct Ingredient {
     pub amount: Option<StringRef>,
+    pub quantity: Option<Quantity>,
     pub stuff: StringRef,
+    pub sub_recipe: bool,
 }
 
 /// Ingredients are stored in a packed array, and rules will in turn
@@ -130,12 +249,32 @@ impl State {
         self.strings.get_or_intern(s)
     }
 
+    /// Find an existing interned string without interning it
+    pub fn lookup_string(&self, s: &str) -> Option<string_interner::DefaultSymbol> {
+        self.strings.get(s)
+    }
+
+    /// Replace the amount of an ingredient that already has one,
+    /// rewriting its text to match. The new text keeps the location
+    /// of the old, so diagnostics still point at the right place.
+    pub fn set_quantity(&mut self, i: IngredientRef, quantity: Quantity) {
+        let value = self.add_string(&quantity.to_string());
+        let ingredient = &mut self[i];
+        if let Some(amount) = ingredient.amount {
+            ingredient.amount = Some(Loc { value, ..amount });
+            ingredient.quantity = Some(quantity);
+        }
+    }
+
     /// Print an `Ingredient` to a writer
     pub fn debug_ingredient(&self, w: &mut impl io::Write, i: &Ingredient) -> io::Result<()> {
         if let Some(amt) = i.amount {
-            write!(w, "[{}] ", &self[amt])?;
+            write!(w, "[{}] ", quote(&self[amt]))?;
         }
-        write!(w, "{}", &self[i.stuff])
+        if i.sub_recipe {
+            write!(w, "@")?;
+        }
+        write!(w, "{}", quote(&self[i.stuff]))
     }
 
     /// Print a sequence of `Ingredient`s to a writer
@@ -167,7 +306,11 @@ impl State {
 
     /// Print an `ActionStep` to a writer
     pub fn debug_action_step(&self, w: &mut impl io::Write, a: &ActionStep) -> io::Result<()> {
-        write!(w, "{}", &self[a.action])?;
+        write!(w, "{}", quote(&self[a.action]))?;
+        if let Some(time) = a.time {
+            let passive = if time.passive { "~" } else { "" };
+            write!(w, " [{}{}]", passive, quote(&self[time.text]))?;
+        }
         if !a.seasonings.is_empty() {
             write!(w, " & ")?;
             self.debug_ingredients(w, &a.seasonings)?;
@@ -178,24 +321,20 @@ impl State {
     /// Print an `Action` to a writer
     pub fn debug_action(&self, w: &mut impl io::Write, a: &Action) -> io::Result<()> {
         match a {
-            Action::Action {
-                step: ActionStep { action, seasonings },
-            } => {
-                write!(w, "{}", &self[*action])?;
-                if !seasonings.is_empty() {
-                    write!(w, " & ")?;
-                    self.debug_ingredients(w, seasonings)?;
-                }
-            }
+            Action::Action { step } => self.debug_action_step(w, step)?,
             Action::Join { point } => write!(w, "{}", &self[*point])?,
-            Action::Done => write!(w, "<>")?,
+            Action::Done { .. } => write!(w, "<>")?,
         }
         Ok(())
     }
 
     /// Print a `Recipe` to a writer
     pub fn debug_recipe(&self, w: &mut impl io::Write, r: &Recipe) -> io::Result<()> {
-        writeln!(w, "{} {{", self.strings.resolve(*r.name).unwrap())?;
+        write!(w, "{} ", quote(&self[r.name]))?;
+        if let Some(servings) = r.servings {
+            write!(w, "[{}] ", quote(&self[servings]))?;
+        }
+        writeln!(w, "{{")?;
         for rule in r.rules.iter() {
             let rule = &self[*rule];
             write!(w, "  ")?;
@@ -208,6 +347,43 @@ impl State {
         }
         writeln!(w, "}}")
     }
+
+    /// Print how we understood every ingredient amount, one per
+    /// line, e.g. `[1 1/2 cups] 3/2 Cup`
+    pub fn debug_quantities(&self, w: &mut impl io::Write) -> io::Result<()> {
+        for i in self.ingredients.iter() {
+            let amt = match i.amount {
+                Some(amt) => amt,
+                None => continue,
+            };
+            write!(w, "[{}] ", &self[amt])?;
+            match &i.quantity {
+                Some(q) => {
+                    match q.value {
+                        Value::Exact(n) => write!(w, "{}", n)?,
+                        Value::Range(lo, hi) => write!(w, "{}..{}", lo, hi)?,
+                    }
+                    if let Some(unit) = &q.unit {
+                        write!(w, " {:?}", unit)?;
+                    }
+                    writeln!(w)?;
+                }
+                None => writeln!(w, "?")?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Print every `Recipe` in a `Cookbook` to a writer
+    pub fn debug_cookbook(&self, w: &mut impl io::Write, c: &Cookbook) -> io::Result<()> {
+        for (i, recipe) in c.recipes.iter().enumerate() {
+            if i > 0 {
+                writeln!(w)?;
+            }
+            self.debug_recipe(w, recipe)?;
+        }
+        Ok(())
+    }
 }
 
 // These allow us to use our `*Ref` types and get the appropriate
@@ -228,6 +404,12 @@ impl Index<IngredientRef> for State {
     }
 }
 
+impl IndexMut<IngredientRef> for State {
+    fn index_mut(&mut self, if_: IngredientRef) -> &mut Self::Output {
+        self.ingredients.index_mut(if_.idx)
+    }
+}
+
 impl Index<StringRef> for State {
     type Output = str;
 
@@ -262,14 +444,14 @@ pub trait ToPrintable: Sized {
 }
 
 impl<'a, T> Printable<'a, T> {
-    pub fn from_val<R>(&self, value: &'a R) -> Printable<R> {
+    pub fn from_val<R>(&self, value: &'a R) -> Printable<'a, R> {
         Printable {
             state: self.state,
             value,
         }
     }
 
-    pub fn from_seq<R>(&self, seq: &'a [R]) -> Vec<Printable<R>> {
+    pub fn from_seq<R>(&self, seq: &'a [R]) -> Vec<Printable<'a, R>> {
         seq.iter().map(|v| self.from_val(v)).collect()
     }
 }
@@ -293,6 +475,9 @@ impl<'a> fmt::Debug for Printable<'a, Ingredient> {
         if let Some(amt) = self.value.amount {
             write!(f, "[{}]", &self.state[amt])?;
         }
+        if self.value.sub_recipe {
+            write!(f, "@")?;
+        }
         write!(f, "{}", &self.state[self.value.stuff])
     }
 }
@@ -0,0 +1,247 @@
+//! The `units` module converts amounts between metric and US
+//! customary units, and can use the density of an ingredient to go
+//! between volume and mass.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::quantity::{Quantity, Rational, Unit, Value};
+use crate::types::{Recipe, State};
+
+/// The set of units we're converting into
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum System {
+    Metric,
+    Us,
+}
+
+impl std::str::FromStr for System {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<System, String> {
+        match s {
+            "metric" => Ok(System::Metric),
+            "us" => Ok(System::Us),
+            _ => Err(format!("unknown unit system '{}'", s)),
+        }
+    }
+}
+
+/// What a unit measures. We can only convert between units of the
+/// same dimension, unless we know the density of the ingredient.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Dimension {
+    Volume,
+    Mass,
+}
+
+impl Unit {
+    /// What this unit measures, along with how many millilitres or
+    /// grams one of it is. Units like `pinch` and units we don't know
+    /// about can't be converted at all.
+    pub fn measure(&self) -> Option<(Dimension, Rational)> {
+        let tsp = Rational::new(492_892, 100_000);
+        let oz = Rational::new(283_495, 10_000);
+        let n = Rational::from_integer;
+        Some(match self {
+            Unit::Teaspoon => (Dimension::Volume, tsp),
+            Unit::Tablespoon => (Dimension::Volume, n(3) * tsp),
+            Unit::FluidOunce => (Dimension::Volume, n(6) * tsp),
+            Unit::Cup => (Dimension::Volume, n(48) * tsp),
+            Unit::Pint => (Dimension::Volume, n(96) * tsp),
+            Unit::Quart => (Dimension::Volume, n(192) * tsp),
+            Unit::Gallon => (Dimension::Volume, n(768) * tsp),
+            Unit::Millilitre => (Dimension::Volume, n(1)),
+            Unit::Litre => (Dimension::Volume, n(1000)),
+            Unit::Milligram => (Dimension::Mass, Rational::new(1, 1000)),
+            Unit::Gram => (Dimension::Mass, n(1)),
+            Unit::Kilogram => (Dimension::Mass, n(1000)),
+            Unit::Ounce => (Dimension::Mass, oz),
+            Unit::Pound => (Dimension::Mass, n(16) * oz),
+            Unit::Pinch | Unit::Dash | Unit::Other(_) => return None,
+        })
+    }
+
+    /// Which system a unit belongs to. Units we can't convert are
+    /// counted as US customary, but nothing looks at them anyway.
+    pub fn system(&self) -> System {
+        match self {
+            Unit::Millilitre | Unit::Litre | Unit::Milligram | Unit::Gram | Unit::Kilogram => {
+                System::Metric
+            }
+            _ => System::Us,
+        }
+    }
+}
+
+/// A problem reading a density table
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("density table line {line}: {message}")]
+pub struct DensityError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// How many grams a millilitre of each ingredient weighs. Ingredients
+/// are looked up by name, ignoring case.
+#[derive(Debug, Clone, Default)]
+pub struct DensityTable {
+    densities: HashMap<String, Rational>,
+}
+
+impl DensityTable {
+    pub fn new() -> DensityTable {
+        DensityTable::default()
+    }
+
+    pub fn insert(&mut self, ingredient: &str, grams_per_ml: Rational) {
+        self.densities
+            .insert(ingredient.trim().to_lowercase(), grams_per_ml);
+    }
+
+    pub fn get(&self, ingredient: &str) -> Option<Rational> {
+        self.densities
+            .get(&ingredient.trim().to_lowercase())
+            .copied()
+    }
+
+    /// Read a density table written one ingredient per line, like
+    /// `flour: 0.53`, where the number is grams per millilitre. Blank
+    /// lines and lines starting with `#` are ignored.
+    pub fn parse(source: &str) -> Result<DensityTable, DensityError> {
+        let mut table = DensityTable::new();
+        for (idx, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| DensityError {
+                line: idx + 1,
+                message: message.to_string(),
+            };
+            let (name, density) = line
+                .rsplit_once(':')
+                .ok_or_else(|| error("expected `ingredient: grams per ml`"))?;
+            let density = Rational::parse(density.trim())
+                .filter(|d| *d > Rational::from_integer(0))
+                .ok_or_else(|| error("density should be a positive number"))?;
+            table.insert(name, density);
+        }
+        Ok(table)
+    }
+}
+
+/// The unit we want to show an amount in, given how much there is in
+/// millilitres or grams, along with how finely to round it
+fn target_unit(system: System, dimension: Dimension, base: Rational) -> (Unit, Rational) {
+    let n = Rational::from_integer;
+    let in_unit = |unit: &Unit| base / unit.measure().unwrap().1;
+    match (system, dimension) {
+        (System::Metric, Dimension::Volume) if base >= n(1000) => {
+            (Unit::Litre, Rational::new(1, 20))
+        }
+        (System::Metric, Dimension::Mass) if base >= n(1000) => {
+            (Unit::Kilogram, Rational::new(1, 20))
+        }
+        (System::Metric, dimension) => {
+            let step = if base < n(10) {
+                Rational::new(1, 2)
+            } else if base < n(100) {
+                n(1)
+            } else {
+                n(5)
+            };
+            match dimension {
+                Dimension::Volume => (Unit::Millilitre, step),
+                Dimension::Mass => (Unit::Gram, step),
+            }
+        }
+        (System::Us, Dimension::Volume) => {
+            if in_unit(&Unit::Cup) >= Rational::new(1, 4) {
+                (Unit::Cup, Rational::new(1, 4))
+            } else if in_unit(&Unit::Tablespoon) >= n(1) {
+                (Unit::Tablespoon, Rational::new(1, 2))
+            } else {
+                (Unit::Teaspoon, Rational::new(1, 8))
+            }
+        }
+        (System::Us, Dimension::Mass) => {
+            if in_unit(&Unit::Pound) >= n(1) {
+                (Unit::Pound, Rational::new(1, 4))
+            } else {
+                (Unit::Ounce, Rational::new(1, 4))
+            }
+        }
+    }
+}
+
+/// Convert a single quantity into the given system. If we know the
+/// ingredient's density, metric recipes get volumes turned into
+/// masses and US recipes get masses turned into volumes, since that's
+/// how cooks in each system usually measure. Quantities we can't
+/// convert, including ones too big to convert exactly, are returned
+/// unchanged.
+pub fn convert(quantity: &Quantity, system: System, density: Option<Rational>) -> Quantity {
+    try_convert(quantity, system, density).unwrap_or_else(|| quantity.clone())
+}
+
+fn try_convert(quantity: &Quantity, system: System, density: Option<Rational>) -> Option<Quantity> {
+    let (dimension, per_unit) = quantity.unit.as_ref().and_then(Unit::measure)?;
+    let (dimension, per_unit) = match (system, dimension, density) {
+        (System::Metric, Dimension::Volume, Some(density)) => {
+            (Dimension::Mass, per_unit.checked_mul(density)?)
+        }
+        (System::Us, Dimension::Mass, Some(density)) => {
+            (Dimension::Volume, per_unit.checked_div(density)?)
+        }
+        // amounts that are already in the right system are left
+        // exactly as the author wrote them
+        _ if quantity.unit.as_ref().map(Unit::system) == Some(system) => return None,
+        _ => (dimension, per_unit),
+    };
+
+    // both ends of a range need to end up in the same unit, so we
+    // pick it based on the larger one
+    let base = quantity.value.max().checked_mul(per_unit)?;
+    let (unit, step) = target_unit(system, dimension, base);
+    let to_unit = per_unit.checked_div(unit.measure().unwrap().1)?;
+    let round = |n: Rational| {
+        let rounded = n.checked_mul(to_unit)?.round_to(step);
+        // don't round anything away to nothing
+        if rounded == Rational::from_integer(0) {
+            Some(step)
+        } else {
+            Some(rounded)
+        }
+    };
+    let value = match quantity.value {
+        Value::Exact(n) => Value::Exact(round(n)?),
+        Value::Range(lo, hi) => Value::Range(round(lo)?, round(hi)?),
+    };
+    Some(Quantity {
+        value,
+        unit: Some(unit),
+    })
+}
+
+/// Rewrite every amount in a recipe into the given system, looking up
+/// each ingredient in `densities` to see if we can convert between
+/// volume and mass
+pub fn convert_recipe(
+    state: &mut State,
+    recipe: &Recipe,
+    system: System,
+    densities: &DensityTable,
+) {
+    for i in recipe.ingredients(state) {
+        let ingredient = &state[i];
+        if let Some(quantity) = &ingredient.quantity {
+            let density = densities.get(&state[ingredient.stuff]);
+            let converted = convert(quantity, system, density);
+            if &converted != quantity {
+                state.set_quantity(i, converted);
+            }
+        }
+    }
+}
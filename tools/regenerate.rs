@@ -1,8 +1,23 @@
 use apicius::{
-    checks, grammar,
-    render::table::Table,
-    types::{State, ToPrintable},
+    checks,
+    quantity::Rational,
+    render::{
+        dot::dot,
+        gantt::Gantt,
+        instructions::Instructions,
+        latex::{latex, LatexOptions},
+        markdown::markdown,
+        mermaid::mermaid,
+        table::Table,
+    },
+    scale,
+    schedule::Schedule,
+    shopping::ShoppingList,
+    timing::{self, Timing},
+    types::{Action, State, ToPrintable},
+    units::{self, DensityTable, System},
 };
+use std::collections::BTreeMap;
 use std::io::Write;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -20,32 +35,293 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             let src = std::fs::read_to_string(&exp)?;
             let mut state = State::new();
-            if let Ok(recipe) = grammar::RecipeParser::new().parse(&mut state, &src) {
-                let mut f = std::fs::File::create(exp_filename("exp"))?;
-                state.debug_recipe(&mut f, &recipe)?;
+            let cookbook = match apicius::parse_cookbook(&mut state, &src) {
+                Ok(cookbook) => cookbook,
+                Err(err) => {
+                    let mut f = std::fs::File::create(exp_filename("parse_error"))?;
+                    write!(f, "{}", err.render(&src))?;
+                    continue;
+                }
+            };
 
-                let mut f = std::fs::File::create(exp_filename("analysis"))?;
-                let a = checks::Analysis::from_recipe(&state, &recipe);
-                write!(f, "{:#?}", a.printable(&state))?;
+            let mut f = std::fs::File::create(exp_filename("exp"))?;
+            state.debug_cookbook(&mut f, &cookbook)?;
 
-                let mut f = std::fs::File::create(exp_filename("problems"))?;
-                a.debug_problems(&mut f, &state)?;
+            // only files which mention amounts get a list of quantities
+            let quantities_path = exp_filename("quantities");
+            let mut buf = Vec::new();
+            state.debug_quantities(&mut buf)?;
+            if !buf.is_empty() {
+                std::fs::write(quantities_path, buf)?;
+            } else if quantities_path.exists() {
+                std::fs::remove_file(quantities_path)?;
+            }
 
-                let bt_path = exp_filename("backward_tree");
-                if let Ok(tree) = a.into_tree() {
-                    {
-                        let mut f = std::fs::File::create(bt_path)?;
-                        write!(f, "{:#?}", tree.printable(&state))?;
+            // recipes that say how many they serve get scaled up by half
+            let scaled_path = exp_filename("scaled");
+            let servings_path = exp_filename("servings");
+            if cookbook.recipes.iter().any(|r| r.servings.is_some()) {
+                let mut f = std::fs::File::create(&servings_path)?;
+                for recipe in cookbook.recipes.iter() {
+                    match scale::servings(&state, recipe) {
+                        Some(n) => writeln!(f, "{}: {}", &state[recipe.name], n)?,
+                        None => writeln!(f, "{}: unknown", &state[recipe.name])?,
                     }
+                }
 
-                    {
-                        let table = Table::new(&state, &tree);
-                        let mut f = std::fs::File::create(exp_filename("raw_table"))?;
-                        write!(f, "{}", table.debug())?;
+                let mut state = State::new();
+                let mut cookbook = apicius::parse_cookbook(&mut state, &src)?;
+                let mut warnings = String::new();
+                for recipe in cookbook.recipes.iter_mut() {
+                    for w in scale::scale(&mut state, recipe, Rational::new(3, 2)) {
+                        warnings.push_str(&w.render(&src));
                     }
-                } else if bt_path.exists() {
-                    std::fs::remove_file(bt_path)?;
                 }
+                let mut f = std::fs::File::create(scaled_path)?;
+                state.debug_cookbook(&mut f, &cookbook)?;
+                write!(f, "\n{}", warnings)?;
+            } else {
+                for path in [scaled_path, servings_path] {
+                    if path.exists() {
+                        std::fs::remove_file(path)?;
+                    }
+                }
+            }
+
+            // recipes with amounts we know how to convert get converted
+            // into both systems, using a density table if there is one
+            let units_path = exp_filename("units");
+            let convertible = cookbook.recipes.iter().any(|r| {
+                r.ingredients(&state).iter().any(|i| {
+                    let unit = state[*i].quantity.as_ref().and_then(|q| q.unit.as_ref());
+                    unit.and_then(|u| u.measure()).is_some()
+                })
+            });
+            if convertible {
+                let densities_path = exp_filename("densities");
+                let densities = if densities_path.exists() {
+                    DensityTable::parse(&std::fs::read_to_string(densities_path)?)?
+                } else {
+                    DensityTable::new()
+                };
+                let mut f = std::fs::File::create(units_path)?;
+                for (name, system) in [("metric", System::Metric), ("us", System::Us)] {
+                    let mut state = State::new();
+                    let cookbook = apicius::parse_cookbook(&mut state, &src)?;
+                    for recipe in cookbook.recipes.iter() {
+                        units::convert_recipe(&mut state, recipe, system, &densities);
+                    }
+                    writeln!(f, "# {}", name)?;
+                    state.debug_cookbook(&mut f, &cookbook)?;
+                    writeln!(f)?;
+                }
+            } else if units_path.exists() {
+                std::fs::remove_file(units_path)?;
+            }
+
+            {
+                let all = checks::Analysis::from_cookbook(&state, &cookbook);
+                let refs: Vec<&checks::Analysis> = all.iter().collect();
+                std::fs::write(exp_filename("dot"), dot(&state, &refs))?;
+            }
+
+            // every other expectation covers each recipe in the file in turn
+            let mut analyses = Vec::new();
+            let mut problems = Vec::new();
+            let mut diagnostics = Vec::new();
+            let mut trees = Vec::new();
+            for a in checks::Analysis::from_cookbook(&state, &cookbook) {
+                analyses.push(format!("{:#?}", a.printable(&state)));
+
+                let mut buf = Vec::new();
+                a.debug_problems(&mut buf, &state)?;
+                problems.push(String::from_utf8(buf)?);
+
+                diagnostics.extend(a.diagnostics(&state).iter().map(|d| d.render(&src)));
+                trees.push(a.into_tree());
+            }
+
+            let mut f = std::fs::File::create(exp_filename("analysis"))?;
+            write!(f, "{}", analyses.join("\n"))?;
+
+            let mut f = std::fs::File::create(exp_filename("problems"))?;
+            write!(f, "{}", problems.join(""))?;
+
+            let diag_path = exp_filename("diagnostics");
+            if !diagnostics.is_empty() {
+                let mut f = std::fs::File::create(diag_path)?;
+                write!(f, "{}", diagnostics.join("\n"))?;
+            } else if diag_path.exists() {
+                std::fs::remove_file(diag_path)?;
+            }
+
+            let bt_path = exp_filename("backward_tree");
+            let trees: Result<Vec<checks::BackwardTree>, _> = trees.into_iter().collect();
+            if let Ok(trees) = trees {
+                {
+                    let printed: Vec<String> = trees
+                        .iter()
+                        .map(|tree| format!("{:#?}", tree.printable(&state)))
+                        .collect();
+                    let mut f = std::fs::File::create(bt_path)?;
+                    write!(f, "{}", printed.join("\n"))?;
+                }
+
+                // only recipes that use other recipes get an inlined tree
+                let inline_path = exp_filename("inlined_tree");
+                let uses_sub_recipes = cookbook
+                    .recipes
+                    .iter()
+                    .any(|r| !r.sub_recipes(&state).is_empty());
+                if uses_sub_recipes {
+                    let by_name: BTreeMap<_, _> = cookbook
+                        .recipes
+                        .iter()
+                        .map(|r| r.name.value)
+                        .zip(trees.iter().cloned())
+                        .collect();
+                    let printed: Vec<String> = trees
+                        .iter()
+                        .map(|tree| {
+                            let inlined = tree.inline_sub_recipes(&state, &by_name);
+                            format!("{:#?}", inlined.printable(&state))
+                        })
+                        .collect();
+                    let mut f = std::fs::File::create(inline_path)?;
+                    write!(f, "{}", printed.join("\n"))?;
+                } else if inline_path.exists() {
+                    std::fs::remove_file(inline_path)?;
+                }
+
+                // only recipes that say how long steps take get timings
+                let timing_path = exp_filename("timing");
+                let has_times = cookbook.recipes.iter().any(|r| {
+                    r.rules.iter().any(|rule| {
+                        state[*rule]
+                            .actions
+                            .iter()
+                            .any(|a| matches!(a, Action::Action { step } if step.time.is_some()))
+                    })
+                });
+                if has_times {
+                    let mut f = std::fs::File::create(timing_path)?;
+                    for (recipe, tree) in cookbook.recipes.iter().zip(trees.iter()) {
+                        for w in timing::unreadable_times(&state, tree) {
+                            write!(f, "{}", w.render(&src))?;
+                        }
+                        writeln!(f, "{}:", &state[recipe.name])?;
+                        Timing::from_tree(tree).debug(&mut f, &state)?;
+                    }
+                } else if timing_path.exists() {
+                    std::fs::remove_file(timing_path)?;
+                }
+
+                // ...and schedules, which we always work out for two cooks
+                let schedule_path = exp_filename("schedule");
+                if has_times {
+                    let mut f = std::fs::File::create(schedule_path)?;
+                    for (recipe, tree) in cookbook.recipes.iter().zip(trees.iter()) {
+                        writeln!(f, "{}:", &state[recipe.name])?;
+                        write!(f, "{}", Schedule::new(tree, 2).text(&state))?;
+                    }
+                } else if schedule_path.exists() {
+                    std::fs::remove_file(schedule_path)?;
+                }
+
+                // ...along with the charts drawn from them
+                for (ext, svg) in [("gantt_svg", true), ("gantt_html", false)] {
+                    let path = exp_filename(ext);
+                    if has_times {
+                        let charts: Vec<String> = trees
+                            .iter()
+                            .map(|tree| {
+                                let plan = Schedule::new(tree, 2);
+                                let gantt = Gantt::new(&state, &plan);
+                                if svg {
+                                    gantt.svg()
+                                } else {
+                                    gantt.html()
+                                }
+                            })
+                            .collect();
+                        std::fs::write(path, charts.join("\n"))?;
+                    } else if path.exists() {
+                        std::fs::remove_file(path)?;
+                    }
+                }
+
+                // the whole file, and then each recipe that uses others
+                // on its own
+                {
+                    let by_name: BTreeMap<_, _> = cookbook
+                        .recipes
+                        .iter()
+                        .map(|r| r.name.value)
+                        .zip(trees.iter().cloned())
+                        .collect();
+                    let all: Vec<_> = cookbook.recipes.iter().collect();
+                    let mut f = std::fs::File::create(exp_filename("shopping_list"))?;
+                    let list = ShoppingList::for_recipes(&state, &cookbook, &by_name, &all);
+                    write!(f, "{}", list.text())?;
+                    for recipe in all.iter() {
+                        if !recipe.sub_recipes(&state).is_empty() {
+                            let list =
+                                ShoppingList::for_recipes(&state, &cookbook, &by_name, &[recipe]);
+                            write!(f, "\n{}:\n{}", &state[recipe.name], list.text())?;
+                        }
+                    }
+                }
+
+                {
+                    let mut f = std::fs::File::create(exp_filename("instructions"))?;
+                    for (recipe, tree) in cookbook.recipes.iter().zip(trees.iter()) {
+                        writeln!(f, "{}:", &state[recipe.name])?;
+                        write!(f, "{}", Instructions::new(&state, tree).text())?;
+                    }
+                }
+
+                {
+                    let pairs: Vec<_> = cookbook.recipes.iter().zip(trees.iter()).collect();
+                    let mut f = std::fs::File::create(exp_filename("mermaid"))?;
+                    write!(f, "{}", mermaid(&state, &pairs, &Default::default()))?;
+                    let mut f = std::fs::File::create(exp_filename("latex"))?;
+                    let opts = LatexOptions {
+                        standalone: false,
+                        graph: true,
+                    };
+                    write!(f, "{}", latex(&state, &pairs, &opts))?;
+                    let mut f = std::fs::File::create(exp_filename("markdown"))?;
+                    write!(f, "{}", markdown(&state, &pairs, &Default::default()))?;
+                }
+
+                {
+                    let mut tables = Vec::new();
+                    for tree in trees.iter() {
+                        tables.push(Table::new(&state, tree).html(&Default::default())?);
+                    }
+                    let mut f = std::fs::File::create(exp_filename("html"))?;
+                    write!(f, "{}", tables.join("\n"))?;
+                }
+
+                {
+                    let tables: Vec<String> = trees
+                        .iter()
+                        .map(|tree| Table::new(&state, tree).debug())
+                        .collect();
+                    let mut f = std::fs::File::create(exp_filename("raw_table"))?;
+                    write!(f, "{}", tables.join("\n"))?;
+                }
+
+                {
+                    let tables: Vec<String> = trees
+                        .iter()
+                        .map(|tree| Table::new(&state, tree).text(&Default::default()))
+                        .collect();
+                    let mut f = std::fs::File::create(exp_filename("text_table"))?;
+                    write!(f, "{}", tables.join("\n"))?;
+                }
+            } else if bt_path.exists() {
+                std::fs::remove_file(bt_path)?;
             }
         }
     }